@@ -0,0 +1,56 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use rust_demo::matcher::PatternSet;
+use rust_demo::search::search_reader;
+use rust_demo::Config;
+
+// about 16 MB of link dump style text, one match every 1000 lines
+fn contents() -> String {
+    let mut contents = String::new();
+    let mut i = 0;
+    while contents.len() < 16 * 1024 * 1024 {
+        if i % 1000 == 999 {
+            contents.push_str("苹果17 ：ProMax  单点链接：https://wx.10086.cn/website/sso/netUni?productCode%3D2025090916421534801379605\n");
+        } else {
+            contents.push_str("【广东】iPhone 17单点链接：https：https://gd.10086.cn/gdshop/qdxsd/index.html#/pageC/zdGoodsDetails\n");
+        }
+        i += 1;
+    }
+    contents
+}
+
+// what search_case_insensitive did before: lowercase both sides on every line
+fn lowercase_every_line<'a>(query: &str, contents: &'a str) -> Vec<(usize, &'a str)> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.to_lowercase().contains(&query.to_lowercase()))
+        .collect()
+}
+
+fn bench_case_insensitive(c: &mut Criterion) {
+    let contents = contents();
+    let mut group = c.benchmark_group("case_insensitive");
+    group.throughput(Throughput::Bytes(contents.len() as u64));
+    group.sample_size(10);
+
+    for query in ["promax", "苹果17 ：promax"] {
+        group.bench_with_input(BenchmarkId::new("to_lowercase_per_line", query), query, |b, query| {
+            b.iter(|| lowercase_every_line(black_box(query), &contents))
+        });
+        group.bench_with_input(BenchmarkId::new("search_case_insensitive", query), query, |b, query| {
+            b.iter(|| Config::search_case_insensitive(black_box(query), &contents))
+        });
+
+        let args: Vec<String> = ["rust_demo", query, "-", "-c"].iter().map(|s| s.to_string()).collect();
+        let config = Config::new(&args).unwrap();
+        let matcher = PatternSet::new(&config).unwrap();
+        group.bench_with_input(BenchmarkId::new("search_reader", query), query, |b, _| {
+            b.iter(|| search_reader(&config, &matcher, contents.as_bytes(), None, &mut std::io::sink()).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_case_insensitive);
+criterion_main!(benches);
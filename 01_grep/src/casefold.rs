@@ -0,0 +1,166 @@
+use std::ops::Range;
+
+use memchr::memmem;
+
+// chars above this have no case mappings
+const LAST_CASED: u32 = 0x1FFFF;
+
+// finds a query ignoring case without allocating per line: the query is
+// lowercased once, candidates are found with SIMD accelerated memchr / memmem
+// scans and only those are compared char by char
+pub struct CaseInsensitive {
+    folded: String,
+    // number of chars in `folded`, a match is never longer than 4 bytes per char
+    folded_chars: usize,
+    scan: Scan,
+}
+
+enum Scan {
+    // the query has no cased letters (digits, CJK, ...), plain substring search
+    Exact(memmem::Finder<'static>),
+    // the query starts with caseless text that must appear exactly
+    Prefix(memmem::Finder<'static>),
+    // the first bytes of every char that lowercases to the first query char
+    FirstByte(Vec<u8>),
+}
+
+impl CaseInsensitive {
+    pub fn new(query: &str) -> CaseInsensitive {
+        let folded: String = query.chars().flat_map(char::to_lowercase).collect();
+        let folded_chars = folded.chars().count();
+        let caseless = folded.char_indices().find(|(_, c)| is_cased(*c)).map_or(folded.len(), |(i, _)| i);
+
+        let scan = if caseless == folded.len() {
+            Scan::Exact(memmem::Finder::new(folded.as_bytes()).into_owned())
+        } else if caseless > 0 {
+            Scan::Prefix(memmem::Finder::new(&folded.as_bytes()[..caseless]).into_owned())
+        } else {
+            let first = folded.chars().next().unwrap();
+            // besides ASCII only U+0130 and the Kelvin sign lowercase to ASCII
+            let candidates: Box<dyn Iterator<Item = u32>> = if first.is_ascii() {
+                Box::new((0..0x80).chain([0x130, 0x212a]))
+            } else {
+                Box::new(0..=LAST_CASED)
+            };
+            let mut starts: Vec<u8> = candidates
+                .filter_map(char::from_u32)
+                .filter(|c| c.to_lowercase().next() == Some(first))
+                .map(|c| c.encode_utf8(&mut [0; 4]).as_bytes()[0])
+                .collect();
+            starts.sort_unstable();
+            starts.dedup();
+            Scan::FirstByte(starts)
+        };
+        CaseInsensitive { folded, folded_chars, scan }
+    }
+
+    pub fn is_match(&self, haystack: &str) -> bool {
+        self.find(haystack.as_bytes()).is_some()
+    }
+
+    // the first match in `haystack`, which need not be valid UTF-8 as a whole
+    pub fn find(&self, haystack: &[u8]) -> Option<Range<usize>> {
+        if self.folded.is_empty() {
+            return Some(0..0);
+        }
+        let mut at = 0;
+        while at < haystack.len() {
+            let candidate = at + self.next_candidate(&haystack[at..])?;
+            if let Scan::Exact(finder) = &self.scan {
+                return Some(candidate..candidate + finder.needle().len());
+            }
+            if let Some(len) = self.match_len(&haystack[candidate..]) {
+                return Some(candidate..candidate + len);
+            }
+            at = candidate + 1;
+        }
+        None
+    }
+
+    // every non-overlapping match in `haystack`
+    pub fn find_iter<'a>(&'a self, haystack: &'a str) -> impl Iterator<Item = Range<usize>> + 'a {
+        let mut at = 0;
+        std::iter::from_fn(move || {
+            if self.folded.is_empty() || at > haystack.len() {
+                return None;
+            }
+            let found = self.find(&haystack.as_bytes()[at..])?;
+            let range = at + found.start..at + found.end;
+            at = range.end;
+            Some(range)
+        })
+    }
+
+    fn next_candidate(&self, haystack: &[u8]) -> Option<usize> {
+        match &self.scan {
+            Scan::Exact(finder) | Scan::Prefix(finder) => finder.find(haystack),
+            Scan::FirstByte(starts) => match starts.as_slice() {
+                [a] => memchr::memchr(*a, haystack),
+                [a, b] => memchr::memchr2(*a, *b, haystack),
+                [a, b, c] => memchr::memchr3(*a, *b, *c, haystack),
+                _ => haystack.iter().position(|b| starts.binary_search(b).is_ok()),
+            },
+        }
+    }
+
+    // length in bytes of the text at the start of `haystack` that lowercases to the query
+    fn match_len(&self, haystack: &[u8]) -> Option<usize> {
+        let window = &haystack[..haystack.len().min(self.folded_chars * 4)];
+        let text = match std::str::from_utf8(window) {
+            Ok(text) => text,
+            Err(err) => std::str::from_utf8(&window[..err.valid_up_to()]).unwrap(),
+        };
+        let mut query = self.folded.chars().peekable();
+        for (start, ch) in text.char_indices() {
+            if query.peek().is_none() {
+                return Some(start);
+            }
+            for lower in ch.to_lowercase() {
+                if query.next() != Some(lower) {
+                    return None;
+                }
+            }
+        }
+        query.peek().is_none().then_some(text.len())
+    }
+}
+
+// whether `c` has another form in another case
+pub fn is_cased(c: char) -> bool {
+    c.to_lowercase().ne(std::iter::once(c)) || c.to_uppercase().ne(std::iter::once(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(query: &str, haystack: &str) -> Option<String> {
+        CaseInsensitive::new(query).find(haystack.as_bytes()).map(|r| haystack[r].to_string())
+    }
+
+    #[test]
+    fn test_find_ignores_case() {
+        assert_eq!(find("rUsT", "Trust me."), Some("rust".into()));
+        assert_eq!(find("promax", "苹果17 ：ProMax  单点"), Some("ProMax".into()));
+        assert_eq!(find("苹果17PROMAX", "【广东】苹果17promax单点"), Some("苹果17promax".into()));
+        assert_eq!(find("ПРО", "iPhone про"), Some("про".into()));
+        // the Kelvin sign lowercases to an ASCII k
+        assert_eq!(find("k", "\u{212a}"), Some("\u{212a}".into()));
+        assert_eq!(find("广东", "福建"), None);
+        assert_eq!(find("duct", "Duc"), None);
+    }
+
+    #[test]
+    fn test_find_in_invalid_utf8() {
+        let finder = CaseInsensitive::new("RUST");
+        assert_eq!(finder.find(b"\xff\xfe rust\xff"), Some(3..7));
+    }
+
+    #[test]
+    fn test_find_iter() {
+        let finder = CaseInsensitive::new("17");
+        assert_eq!(finder.find_iter("苹果17 iPhone 17").collect::<Vec<_>>(), vec![6..8, 16..18]);
+        let finder = CaseInsensitive::new("a");
+        assert_eq!(finder.find_iter("AaA").count(), 3);
+    }
+}
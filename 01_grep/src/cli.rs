@@ -0,0 +1,248 @@
+use chrono::NaiveDateTime;
+use clap::{Parser, Subcommand};
+
+use crate::{color, dates, scope};
+
+// the command line as clap sees it; `Config::new` checks it and turns it
+// into a `Config`
+#[derive(Parser, Debug)]
+#[command(
+    name = "rust_demo",
+    version,
+    about = "Search files for lines that match a pattern",
+    override_usage = "rust_demo [OPTIONS] PATTERN [PATH]...\n       rust_demo [OPTIONS] -e PATTERN... [PATH]...\n       rust_demo [OPTIONS] -f FILE... [PATH]...\n       rust_demo index build [DIR]\n       rust_demo index query [OPTIONS] PATTERN [DIR]..."
+)]
+pub struct Args {
+    #[arg(value_name = "PATTERN | PATH", help = "The pattern, unless -e or -f is given, then the files and directories to search (- for stdin)")]
+    pub positional: Vec<String>,
+
+    #[arg(short = 'e', long = "regexp", value_name = "PATTERN", allow_hyphen_values = true, help = "A pattern to search for, can be repeated")]
+    pub patterns: Vec<String>,
+
+    #[arg(short = 'f', long = "file", value_name = "FILE", help = "Read patterns from FILE, one per line")]
+    pub pattern_files: Vec<String>,
+
+    #[arg(short = 'n', long = "line-number", help = "Prefix each line with its line number")]
+    pub line_number: bool,
+
+    #[arg(short = 's', long = "case-sensitive", help = "Match case exactly")]
+    pub case_sensitive: bool,
+
+    #[arg(short = 'E', long = "regex", help = "Patterns are regular expressions")]
+    pub regex: bool,
+
+    #[arg(short = 'v', long = "invert-match", help = "Select lines that do not match")]
+    pub invert: bool,
+
+    #[arg(short = 'c', long = "count", help = "Print only the number of selected lines per file")]
+    pub count: bool,
+
+    #[arg(short = 'l', long = "files-with-matches", help = "Print only the names of files with a match")]
+    pub files_with_matches: bool,
+
+    #[arg(short = 'L', long = "files-without-match", help = "Print only the names of files without a match")]
+    pub files_without_match: bool,
+
+    #[arg(short = 'o', long = "only-matching", help = "Print only the matched parts of a line")]
+    pub only_matching: bool,
+
+    #[arg(short = 'm', long = "max-count", value_name = "NUM", help = "Stop reading a file after NUM selected lines")]
+    pub max_count: Option<usize>,
+
+    #[arg(short = 'A', long = "after-context", value_name = "NUM", help = "Print NUM lines after each match")]
+    pub after_context: Option<usize>,
+
+    #[arg(short = 'B', long = "before-context", value_name = "NUM", help = "Print NUM lines before each match")]
+    pub before_context: Option<usize>,
+
+    #[arg(short = 'C', long = "context", value_name = "NUM", help = "Print NUM lines around each match")]
+    pub context: Option<usize>,
+
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "count", "files_with_matches", "files_without_match", "only_matching", "json", "since", "until", "replace", "max_count",
+            "context", "before_context", "after_context",
+        ],
+        help = "Keep watching the file for appended lines"
+    )]
+    pub follow: bool,
+
+    #[arg(long, value_name = "LABEL", help = "Read input in this encoding instead of detecting it (utf-8, gbk, utf-16le, ...)")]
+    pub encoding: Option<String>,
+
+    #[arg(long, help = "Match full width and compatibility characters as their plain forms")]
+    pub normalize: bool,
+
+    #[arg(long, help = "Print one JSON record per match")]
+    pub json: bool,
+
+    #[arg(
+        long,
+        value_name = "TEMPLATE",
+        conflicts_with_all = [
+            "invert", "max_count", "count", "files_with_matches", "files_without_match", "only_matching", "json", "section", "in_section",
+            "since", "until",
+        ],
+        help = "Replace matches with TEMPLATE ($1, ${name}) and print a diff"
+    )]
+    pub replace: Option<String>,
+
+    #[arg(long, requires = "replace", help = "Rewrite the files instead of printing a diff")]
+    pub in_place: bool,
+
+    #[arg(long, requires = "in_place", help = "Keep a .bak copy of every rewritten file")]
+    pub backup: bool,
+
+    #[arg(long, value_name = "N", conflicts_with_all = ["regex", "replace"], help = "Match patterns with up to N inserted, deleted or changed chars")]
+    pub fuzzy: Option<usize>,
+
+    #[arg(
+        long,
+        requires = "fuzzy",
+        conflicts_with_all = ["invert", "count", "files_with_matches", "files_without_match", "only_matching", "json", "context", "before_context", "after_context", "follow"],
+        help = "Print the matching lines of all files closest first"
+    )]
+    pub rank: bool,
+
+    #[arg(
+        long,
+        value_name = "REGEX",
+        conflicts_with_all = ["context", "before_context", "after_context", "rank", "follow"],
+        help = "Lines matching REGEX are section headings, each match is printed under its heading"
+    )]
+    pub section: Option<String>,
+
+    #[arg(long, value_name = "TEXT", requires = "section", help = "Only search sections whose heading contains TEXT, can be repeated")]
+    pub in_section: Vec<String>,
+
+    #[arg(
+        long,
+        conflicts_with_all = ["invert", "only_matching", "context", "before_context", "after_context", "replace", "rank", "follow"],
+        help = "Print the links in matching lines with their percent-decoded query parameters"
+    )]
+    pub urls: bool,
+
+    #[arg(long = "param", value_name = "NAME[=VALUE]", requires = "urls", help = "Only print this parameter, or only links where it has VALUE; can be repeated")]
+    pub params: Vec<String>,
+
+    #[arg(long, value_name = "WHEN", value_parser = dates::parse_when, help = "In directories, only search files changed after WHEN (2h, 3d, 2025-09-09, 2025-09-09 16:30)")]
+    pub newer_than: Option<NaiveDateTime>,
+
+    #[arg(long, value_name = "WHEN", value_parser = dates::parse_when, help = "In directories, only search files changed before WHEN")]
+    pub older_than: Option<NaiveDateTime>,
+
+    #[arg(
+        short = 'g',
+        long = "glob",
+        value_name = "GLOB",
+        allow_hyphen_values = true,
+        help = "In directories, only search files matching GLOB, or skip them with !GLOB; can be repeated"
+    )]
+    pub globs: Vec<String>,
+
+    #[arg(
+        short = 't',
+        long = "type",
+        value_name = "TYPE",
+        value_delimiter = ',',
+        help = "In directories, only search files of these types, like css,js,html; can be repeated"
+    )]
+    pub types: Vec<String>,
+
+    #[arg(long, help = "In directories, also search files that look binary (a NUL byte near the start)")]
+    pub binary: bool,
+
+    #[arg(
+        long,
+        value_name = "WHEN",
+        value_parser = dates::parse_when,
+        conflicts_with = "rank",
+        help = "Log mode: only lines stamped at or after WHEN, unstamped lines go with the line above"
+    )]
+    pub since: Option<NaiveDateTime>,
+
+    #[arg(long, value_name = "WHEN", value_parser = dates::parse_when, conflicts_with = "rank", help = "Log mode: only lines stamped before WHEN")]
+    pub until: Option<NaiveDateTime>,
+
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "pattern_files", "count", "files_with_matches", "files_without_match", "only_matching", "json", "context", "before_context",
+            "after_context", "follow", "replace", "rank", "section", "urls", "since", "until",
+        ],
+        help = "Search again on every keystroke; the positionals are all paths, -e sets the first query"
+    )]
+    pub interactive: bool,
+
+    #[arg(
+        long,
+        value_name = "WHEN",
+        value_enum,
+        default_value_t = color::When::Auto,
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "auto",
+        help = "Highlight matches, paths and line numbers: auto (on a terminal, unless NO_COLOR is set), always or never"
+    )]
+    pub color: color::When,
+
+    #[arg(
+        short = 'U',
+        long,
+        conflicts_with_all = [
+            "invert", "context", "before_context", "after_context", "fuzzy", "normalize", "section", "urls", "replace", "follow",
+            "interactive", "since", "until",
+        ],
+        help = "Matches may span lines: whitespace in a pattern also matches line ends, -E patterns can use \\n"
+    )]
+    pub multiline: bool,
+
+    #[arg(
+        long,
+        value_name = "EXPR",
+        conflicts_with_all = ["patterns", "pattern_files", "multiline", "replace", "rank", "interactive"],
+        help = "Search for terms joined by AND, OR, NOT and parentheses, like '(iPhone OR 苹果) AND 17 NOT 广东'; the positionals are all paths"
+    )]
+    pub query: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "SCOPE",
+        value_enum,
+        conflicts_with_all = ["context", "before_context", "after_context", "urls", "since", "until", "multiline", "follow", "rank", "replace", "interactive"],
+        help = "What has to match: each line (the default), each paragraph between blank lines, or each --section; matching blocks print whole"
+    )]
+    pub scope: Option<scope::Scope>,
+}
+
+// `rust_demo index build|query ...`; `index` is only a command when one of those
+// follows it, so `rust_demo index a.txt` still searches for `index`
+pub fn is_index_command(args: &[String]) -> bool {
+    args.get(1).is_some_and(|arg| arg == "index") && args.get(2).is_some_and(|arg| arg == "build" || arg == "query")
+}
+
+#[derive(Parser, Debug)]
+#[command(name = "rust_demo index", about = "Keep a trigram index of a directory for fast repeated searches", disable_help_subcommand = true)]
+pub struct IndexArgs {
+    #[command(subcommand)]
+    pub command: IndexCommand,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum IndexCommand {
+    #[command(about = "Index DIR, or bring its index up to date by reading only the files that changed")]
+    Build {
+        #[arg(default_value = ".")]
+        dir: String,
+    },
+    #[command(
+        about = "Search like rust_demo does, only reading the files the index says can match",
+        override_usage = "rust_demo index query [OPTIONS] PATTERN [DIR]..."
+    )]
+    Query {
+        // every search option works, they are parsed like a plain search
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+}
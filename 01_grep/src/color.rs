@@ -0,0 +1,81 @@
+use std::env;
+use std::io::{self, IsTerminal};
+use std::ops::Range;
+
+use clap::ValueEnum;
+
+// SGR codes, the ones grep uses by default
+pub const MATCH: &str = "1;31";
+pub const PATH: &str = "35";
+pub const LINE_NUMBER: &str = "32";
+pub const SEPARATOR: &str = "36";
+
+// --color
+#[derive(Debug, Default, Clone, Copy, PartialEq, ValueEnum)]
+pub enum When {
+    // only when stdout is a terminal and NO_COLOR is not set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+impl When {
+    // settle `auto` against the real stdout; an explicit choice wins over NO_COLOR
+    pub fn resolve(self) -> When {
+        let no_color = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        self.resolve_for(io::stdout().is_terminal(), no_color)
+    }
+
+    fn resolve_for(self, is_terminal: bool, no_color: bool) -> When {
+        match self {
+            When::Auto if is_terminal && !no_color => When::Always,
+            When::Auto => When::Never,
+            when => when,
+        }
+    }
+}
+
+pub fn paint(code: &str, text: &str) -> String {
+    format!("\x1b[{}m{}\x1b[0m", code, text)
+}
+
+// `text` with the byte ranges in `matches` painted, empty matches have nothing to
+// paint; the part of a range that overlaps one painted before it is left out
+pub fn highlight(text: &str, matches: &[Range<usize>]) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut pos = 0;
+    for range in matches.iter() {
+        let start = range.start.max(pos);
+        if start >= range.end {
+            continue;
+        }
+        out.push_str(&text[pos..start]);
+        out.push_str(&paint(MATCH, &text[start..range.end]));
+        pos = range.end;
+    }
+    out.push_str(&text[pos..]);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        assert_eq!(When::Auto.resolve_for(true, false), When::Always);
+        assert_eq!(When::Auto.resolve_for(false, false), When::Never);
+        assert_eq!(When::Auto.resolve_for(true, true), When::Never);
+        assert_eq!(When::Always.resolve_for(false, true), When::Always);
+        assert_eq!(When::Never.resolve_for(true, false), When::Never);
+    }
+
+    #[test]
+    fn test_highlight() {
+        assert_eq!(highlight("苹果17 苹果", &[0..6, 9..9, 9..15]), "\x1b[1;31m苹果\x1b[0m17 \x1b[1;31m苹果\x1b[0m");
+        assert_eq!(highlight("promax", &[]), "promax");
+        // overlapping and out of order ranges do not panic
+        assert_eq!(highlight("promax", &[0..3, 1..3, 2..5, 0..1]), "\x1b[1;31mpro\x1b[0m\x1b[1;31mma\x1b[0mx");
+    }
+}
@@ -0,0 +1,121 @@
+use std::collections::VecDeque;
+
+// a line to print: either a match or a context line around one
+#[derive(Debug, PartialEq)]
+pub struct Line<'a> {
+    pub index: usize,
+    pub text: &'a str,
+    pub is_match: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Event<'a> {
+    Line(Line<'a>),
+    // the next line does not follow the previous one, grep prints `--`
+    Break,
+}
+
+// tracks `before` / `after` lines of context while lines stream past;
+// only the last `before` lines are kept, so memory stays bounded
+pub struct Context {
+    before: usize,
+    after: usize,
+    buffer: VecDeque<(usize, String)>,
+    after_left: usize,
+    last_printed: Option<usize>,
+}
+
+impl Context {
+    pub fn new(before: usize, after: usize) -> Context {
+        Context { before, after, buffer: VecDeque::with_capacity(before), after_left: 0, last_printed: None }
+    }
+
+    // feed the next line, `emit` gets whatever should be printed because of it
+    pub fn push<F>(&mut self, index: usize, text: &str, is_match: bool, mut emit: F)
+    where
+        F: FnMut(Event),
+    {
+        if is_match {
+            let first = self.buffer.front().map_or(index, |(i, _)| *i);
+            if self.has_context() && self.last_printed.is_some_and(|last| first > last + 1) {
+                emit(Event::Break);
+            }
+            for (i, line) in self.buffer.drain(..) {
+                emit(Event::Line(Line { index: i, text: &line, is_match: false }));
+            }
+            emit(Event::Line(Line { index, text, is_match: true }));
+            self.after_left = self.after;
+            self.last_printed = Some(index);
+        } else if self.after_left > 0 {
+            emit(Event::Line(Line { index, text, is_match: false }));
+            self.after_left -= 1;
+            self.last_printed = Some(index);
+        } else if self.before > 0 {
+            if self.buffer.len() == self.before {
+                self.buffer.pop_front();
+            }
+            self.buffer.push_back((index, text.to_string()));
+        }
+    }
+
+    // true while lines of trailing context are still owed to the last match
+    pub fn wants_more(&self) -> bool {
+        self.after_left > 0
+    }
+
+    pub fn has_context(&self) -> bool {
+        self.before > 0 || self.after > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // run lines through a Context, `None` stands for a break
+    fn collect(lines: &[&str], matches: &[usize], before: usize, after: usize) -> Vec<Option<(usize, bool)>> {
+        let mut context = Context::new(before, after);
+        let mut out = Vec::new();
+        for (index, text) in lines.iter().enumerate() {
+            context.push(index, text, matches.contains(&index), |event| match event {
+                Event::Line(line) => out.push(Some((line.index, line.is_match))),
+                Event::Break => out.push(None),
+            });
+        }
+        out
+    }
+
+    #[test]
+    fn test_context_merge_and_split() {
+        let lines: Vec<&str> = (0..12).map(|_| "x").collect();
+        assert_eq!(
+            collect(&lines, &[2, 4, 10], 1, 1),
+            vec![
+                Some((1, false)),
+                Some((2, true)),
+                Some((3, false)),
+                Some((4, true)),
+                Some((5, false)),
+                None,
+                Some((9, false)),
+                Some((10, true)),
+                Some((11, false)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_context_adjacent_windows_join() {
+        let lines = vec!["【广东】", "a", "b", "【福建】", "c"];
+        // line 2 is in neither window, so the groups stay apart
+        assert_eq!(collect(&lines, &[1, 4], 1, 0), vec![Some((0, false)), Some((1, true)), None, Some((3, false)), Some((4, true))]);
+        // windows that touch are printed as one group
+        assert_eq!(collect(&lines, &[1, 3], 1, 0), vec![Some((0, false)), Some((1, true)), Some((2, false)), Some((3, true))]);
+    }
+
+    #[test]
+    fn test_context_no_breaks_without_context() {
+        let lines = vec!["a", "b", "a"];
+        assert_eq!(collect(&lines, &[0, 2], 0, 0), vec![Some((0, true)), Some((2, true))]);
+    }
+}
@@ -0,0 +1,133 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeDelta};
+use regex::Regex;
+
+// formats accepted for a point in time, read as local time
+const FORMATS: [&str; 4] = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"];
+
+// a span of time, `since` is inclusive and `until` exclusive, so
+// `--until 2025-09-10` keeps everything up to the end of the 9th
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Window {
+    pub since: Option<NaiveDateTime>,
+    pub until: Option<NaiveDateTime>,
+}
+
+impl Window {
+    pub fn is_unbounded(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+
+    pub fn contains(&self, time: NaiveDateTime) -> bool {
+        self.since.is_none_or(|since| time >= since) && self.until.is_none_or(|until| time < until)
+    }
+}
+
+// for clap: `30m`, `2h`, `3d`, `1w` before now, or a date with an optional time
+pub fn parse_when(text: &str) -> Result<NaiveDateTime, String> {
+    parse_when_at(text, Local::now().naive_local())
+}
+
+pub fn parse_when_at(text: &str, now: NaiveDateTime) -> Result<NaiveDateTime, String> {
+    let text = text.trim();
+    if let Some(unit) = text.chars().last().filter(|c| c.is_ascii_alphabetic()) {
+        let amount: i64 = text[..text.len() - 1].parse().map_err(|_| format!("bad duration `{}`", text))?;
+        let duration = match unit {
+            's' => TimeDelta::try_seconds(amount),
+            'm' => TimeDelta::try_minutes(amount),
+            'h' => TimeDelta::try_hours(amount),
+            'd' => TimeDelta::try_days(amount),
+            'w' => TimeDelta::try_weeks(amount),
+            _ => return Err(format!("unknown unit in `{}`, use s, m, h, d or w", text)),
+        };
+        return duration
+            .and_then(|duration| now.checked_sub_signed(duration))
+            .ok_or_else(|| format!("`{}` is out of range", text));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(text, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap());
+    }
+    FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(text, format).ok())
+        .ok_or_else(|| format!("`{}` is not a duration like 2h or a date like 2025-09-09 16:30", text))
+}
+
+// the timestamp a log line starts with, `[...]` around it is allowed; one with
+// a UTC offset is turned into local time so it compares with the window
+pub fn line_timestamp(line: &str) -> Option<NaiveDateTime> {
+    static STAMP: OnceLock<Regex> = OnceLock::new();
+    let stamp = STAMP.get_or_init(|| {
+        Regex::new(r"^\[?(\d{4})[-/](\d{2})[-/](\d{2})[T ](\d{2}:\d{2}:\d{2})(\.\d+)?(Z|[+-]\d{2}:?\d{2})?").unwrap()
+    });
+    let caps = stamp.captures(line)?;
+    let text = format!("{}-{}-{} {}{}", &caps[1], &caps[2], &caps[3], &caps[4], caps.get(5).map_or("", |m| m.as_str()));
+    let time = NaiveDateTime::parse_from_str(&text, "%Y-%m-%d %H:%M:%S%.f").ok()?;
+    let offset = match caps.get(6).map(|m| m.as_str()) {
+        None => return Some(time),
+        Some("Z") => FixedOffset::east_opt(0)?,
+        Some(offset) => {
+            let digits = offset[1..].replace(':', "");
+            let seconds = digits[..2].parse::<i32>().ok()? * 3600 + digits[2..].parse::<i32>().ok()? * 60;
+            if offset.starts_with('-') { FixedOffset::west_opt(seconds)? } else { FixedOffset::east_opt(seconds)? }
+        }
+    };
+    let time: DateTime<FixedOffset> = time.and_local_timezone(offset).single()?;
+    Some(time.with_timezone(&Local).naive_local())
+}
+
+// when `path` was last written, in local time
+pub fn modified(path: &Path) -> io::Result<NaiveDateTime> {
+    let modified: DateTime<Local> = fs::metadata(path)?.modified()?.into();
+    Ok(modified.naive_local())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn test_parse_when() {
+        let now = at("2025-09-09 16:36:53");
+        assert_eq!(parse_when_at("2h", now), Ok(at("2025-09-09 14:36:53")));
+        assert_eq!(parse_when_at("3d", now), Ok(at("2025-09-06 16:36:53")));
+        assert_eq!(parse_when_at("2025-09-01", now), Ok(at("2025-09-01 00:00:00")));
+        assert_eq!(parse_when_at("2025-09-01T08:30", now), Ok(at("2025-09-01 08:30:00")));
+        assert!(parse_when_at("3y", now).is_err());
+        assert!(parse_when_at("yesterday", now).is_err());
+        // out of range is an error, not a panic
+        assert!(parse_when_at("999999999d", now).is_err());
+        assert!(parse_when_at("9223372036854775807w", now).is_err());
+        assert!(parse_when_at("9223372036854775807s", now).is_err());
+        assert!(parse_when_at("-999999999999h", now).is_err());
+    }
+
+    #[test]
+    fn test_line_timestamp() {
+        assert_eq!(line_timestamp("2025-09-09 16:36:53 INFO start"), Some(at("2025-09-09 16:36:53")));
+        assert_eq!(line_timestamp("[2025/09/09 16:36:53.123] 苹果17"), Some(at("2025-09-09 16:36:53") + TimeDelta::milliseconds(123)));
+        assert_eq!(line_timestamp("    at com.example.Main"), None);
+        assert_eq!(line_timestamp("order 2025-09-09 16:36:53"), None);
+
+        let utc = line_timestamp("2025-09-09T08:36:53Z x").unwrap();
+        let east = line_timestamp("2025-09-09T16:36:53+08:00 x").unwrap();
+        assert_eq!(utc, east);
+    }
+
+    #[test]
+    fn test_window() {
+        let window = Window { since: Some(at("2025-09-09 00:00:00")), until: Some(at("2025-09-10 00:00:00")) };
+        assert!(window.contains(at("2025-09-09 00:00:00")));
+        assert!(window.contains(at("2025-09-09 23:59:59")));
+        assert!(!window.contains(at("2025-09-10 00:00:00")));
+        assert!(Window::default().is_unbounded());
+    }
+}
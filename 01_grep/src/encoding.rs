@@ -0,0 +1,187 @@
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use encoding_rs::{DecoderResult, Encoding, GB18030, UTF_16BE, UTF_16LE, UTF_8};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+
+// how much of the input is looked at to guess its encoding
+const SAMPLE_SIZE: usize = 64 * 1024;
+// how much of a file is looked at to tell whether it is binary, as grep does
+const BINARY_SAMPLE_SIZE: u64 = 8 * 1024;
+
+// look up an `--encoding` value such as `gbk`, `gb18030`, `utf-16le` or `utf-8`
+pub fn for_label(label: &str) -> Option<&'static Encoding> {
+    Encoding::for_label(label.trim().as_bytes())
+}
+
+// guess the encoding of the start of a file: a BOM wins, then UTF-16 if every
+// other byte is NUL. GB18030 (a superset of GBK), which is what our non UTF-8
+// text files are, only when it decodes cleanly and most multibyte sequences are
+// not UTF-8; anything else is UTF-8, a stray invalid byte gets replaced
+pub fn detect(sample: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(sample) {
+        return encoding;
+    }
+    let pairs = sample.len() / 2;
+    if pairs > 0 {
+        let even = sample.iter().step_by(2).filter(|&&b| b == 0).count();
+        let odd = sample.iter().skip(1).step_by(2).filter(|&&b| b == 0).count();
+        // mostly ASCII text in UTF-16 has a NUL in one half of every pair
+        if odd * 10 > pairs * 3 && even * 10 < pairs {
+            return UTF_16LE;
+        }
+        if even * 10 > pairs * 3 && odd * 10 < pairs {
+            return UTF_16BE;
+        }
+    }
+    let (valid, invalid) = utf8_sequences(sample);
+    if invalid > valid && is_gb18030(sample) {
+        return GB18030;
+    }
+    UTF_8
+}
+
+// (characters of two or more bytes, invalid sequences) of `sample` read as UTF-8
+fn utf8_sequences(mut sample: &[u8]) -> (usize, usize) {
+    let multibyte = |text: &str| text.chars().filter(|c| c.len_utf8() > 1).count();
+    let (mut valid, mut invalid) = (0, 0);
+    loop {
+        match std::str::from_utf8(sample) {
+            Ok(text) => return (valid + multibyte(text), invalid),
+            Err(err) => {
+                let (good, rest) = sample.split_at(err.valid_up_to());
+                valid += multibyte(std::str::from_utf8(good).unwrap_or_default());
+                match err.error_len() {
+                    Some(len) => {
+                        invalid += 1;
+                        sample = &rest[len..];
+                    }
+                    // the sample may end in the middle of a character
+                    None => return (valid, invalid),
+                }
+            }
+        }
+    }
+}
+
+// `sample` decoded, unless some of it is not valid in `encoding`
+fn decode_cleanly(encoding: &'static Encoding, sample: &[u8]) -> Option<String> {
+    let mut decoder = encoding.new_decoder_without_bom_handling();
+    let mut text = String::with_capacity(decoder.max_utf8_buffer_length_without_replacement(sample.len())?);
+    // not the last input, a character cut off at the end of the sample is fine
+    let (result, _) = decoder.decode_to_string_without_replacement(sample, &mut text, false);
+    (result == DecoderResult::InputEmpty).then_some(text)
+}
+
+fn is_gb18030(sample: &[u8]) -> bool {
+    decode_cleanly(GB18030, sample).is_some()
+}
+
+// a NUL byte means binary data like an image, unless the text is UTF-16
+// where half the bytes of ASCII text are NUL. Without a BOM UTF-16 is only a
+// guess, so the sample also has to be whole 16-bit units that decode to text
+// without control characters, which 16-bit pixel data does not
+pub fn is_binary(sample: &[u8], forced: Option<&'static Encoding>) -> bool {
+    if memchr::memchr(0, sample).is_none() {
+        return false;
+    }
+    let encoding = forced.unwrap_or_else(|| detect(sample));
+    if encoding != UTF_16LE && encoding != UTF_16BE {
+        return true;
+    }
+    Encoding::for_bom(sample).is_none() && !is_utf16_text(encoding, sample)
+}
+
+fn is_utf16_text(encoding: &'static Encoding, sample: &[u8]) -> bool {
+    let is_text = |c: char| !c.is_control() || matches!(c, '\t' | '\n' | '\r' | '\x0c');
+    sample.len().is_multiple_of(2) && decode_cleanly(encoding, sample).is_some_and(|text| text.chars().all(is_text))
+}
+
+pub fn is_binary_file(path: &Path, forced: Option<&'static Encoding>) -> io::Result<bool> {
+    let mut sample = Vec::new();
+    File::open(path)?.take(BINARY_SAMPLE_SIZE).read_to_end(&mut sample)?;
+    Ok(is_binary(&sample, forced))
+}
+
+// wrap `reader` so it yields UTF-8, either in the given encoding or in the
+// one detected from the first bytes; UTF-8 input is passed through untouched
+pub fn decode_reader<'a, R: Read + 'a>(reader: R, forced: Option<&'static Encoding>) -> io::Result<Box<dyn BufRead + 'a>> {
+    let mut reader = BufReader::with_capacity(SAMPLE_SIZE, reader);
+    let encoding = match forced {
+        Some(encoding) => encoding,
+        None => detect(reader.fill_buf()?),
+    };
+
+    let decoder = DecodeReaderBytesBuilder::new()
+        .encoding(Some(encoding))
+        .bom_override(forced.is_none())
+        .strip_bom(true)
+        .utf8_passthru(true)
+        .build(reader);
+    Ok(Box::new(BufReader::new(decoder)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(bytes: &[u8], forced: Option<&'static Encoding>) -> String {
+        let mut out = String::new();
+        decode_reader(bytes, forced).unwrap().read_to_string(&mut out).unwrap();
+        out
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect("【广东】苹果17".as_bytes()), UTF_8);
+        assert_eq!(detect(b"\xef\xbb\xbfabc"), UTF_8);
+        assert_eq!(detect(b"\xff\xfea\x00b\x00"), UTF_16LE);
+        assert_eq!(detect(b"a\x00b\x00c\x00d\x00"), UTF_16LE);
+        assert_eq!(detect(b"\x00a\x00b\x00c\x00d"), UTF_16BE);
+        // the sample stops halfway through "东"
+        assert_eq!(detect(&"广东".as_bytes()[..5]), UTF_8);
+        let (gbk, _, _) = GB18030.encode("【广东】苹果17");
+        assert_eq!(detect(&gbk), GB18030);
+        // a GBK sample cut off halfway through "果"
+        assert_eq!(detect(&gbk[..gbk.len() - 3]), GB18030);
+    }
+
+    #[test]
+    fn test_detect_stray_byte() {
+        // mostly UTF-8 with a stray Latin-1 byte is still UTF-8
+        assert_eq!(detect(b"\xe8\x8b\xb9\xe6\x9e\x9c foo\nlatin1 caf\xe9\n"), UTF_8);
+        assert_eq!(detect(b"caf\xe9\n"), UTF_8);
+        assert_eq!(decode(b"\xe8\x8b\xb9\xe6\x9e\x9c caf\xe9\n", None), "苹果 caf\u{fffd}\n");
+    }
+
+    #[test]
+    fn test_decode_reader() {
+        let (gbk, _, _) = GB18030.encode("【福建】\niPhone 17\n");
+        assert_eq!(decode(&gbk, None), "【福建】\niPhone 17\n");
+        assert_eq!(decode(b"\xff\xfe\x7f\x5e\x1c\x4e", None), "广东");
+        assert_eq!(decode(b"\xef\xbb\xbfplain", None), "plain");
+        // a forced encoding is used even where detection would pick another
+        assert_eq!(decode(&gbk, for_label("gbk")), "【福建】\niPhone 17\n");
+        assert_eq!(decode("广东".as_bytes(), for_label("utf-8")), "广东");
+        assert!(for_label("no-such-encoding").is_none());
+    }
+
+    #[test]
+    fn test_is_binary() {
+        assert!(is_binary(b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR", None));
+        assert!(!is_binary("【广东】苹果17\n".as_bytes(), None));
+        assert!(!is_binary(b"\xff\xfea\x00b\x00", None));
+        assert!(!is_binary(b"a\x00b\x00c\x00d\x00", None));
+        // a forced UTF-8 makes those NULs binary again
+        assert!(is_binary(b"a\x00b\x00c\x00d\x00", for_label("utf-8")));
+        assert!(!is_binary("广东\r\n".encode_utf16().flat_map(u16::to_be_bytes).collect::<Vec<_>>().as_slice(), None));
+        // NULs that only look like UTF-16: an odd length, control characters
+        // like those of 16-bit pixel data, a lone surrogate
+        assert!(is_binary(b"x\x00\x01", None));
+        assert!(is_binary(b"\x10\x00\x20\x00\x30\x00\x40\x00", None));
+        assert!(is_binary(b"a\x00b\x00c\x00d\x00\x00\xdce\x00f\x00g\x00h\x00i\x00j\x00", None));
+        // unless a BOM says it is UTF-16
+        assert!(!is_binary(b"\xff\xfe\x10\x00\x20\x00", None));
+    }
+}
@@ -0,0 +1,114 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// everything that can go wrong, from the command line to reading files
+#[derive(Debug)]
+pub enum Error {
+    // clap could not parse the command line, also carries --help / --version
+    Args(clap::Error),
+    // no pattern: no positional argument and no -e / -f
+    MissingArgs,
+    UnknownEncoding(String),
+    // flags that cannot be used together, or not on these inputs
+    Usage(&'static str),
+    Pattern(regex::Error),
+    // a --query that does not parse, and why
+    Query(String),
+    Literals(aho_corasick::BuildError),
+    // `path` is the file being read or written, when there is one
+    Io { path: Option<PathBuf>, source: io::Error },
+    Walk(ignore::Error),
+    Watch(notify::Error),
+    // files of a directory search that failed, each one was reported already
+    Unreadable(usize),
+    // `index query` on a directory that has no index yet
+    NoIndex(PathBuf),
+    // the index file is not one this version wrote
+    BadIndex(PathBuf),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn io(path: &Path, source: io::Error) -> Error {
+        Error::Io { path: Some(path.to_path_buf()), source }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Args(err) => write!(f, "{}", err),
+            Error::MissingArgs => write!(f, "not enough arguments"),
+            Error::UnknownEncoding(label) => write!(f, "unknown encoding: {}", label),
+            Error::Usage(message) => write!(f, "{}", message),
+            Error::Pattern(err) => write!(f, "bad pattern: {}", err),
+            Error::Query(message) => write!(f, "bad query: {}", message),
+            Error::Literals(err) => write!(f, "bad pattern: {}", err),
+            Error::Io { path: Some(path), source } => write!(f, "{}: {}", path.display(), source),
+            Error::Io { path: None, source } => write!(f, "{}", source),
+            Error::Walk(err) => write!(f, "{}", err),
+            Error::Watch(err) => write!(f, "{}", err),
+            Error::Unreadable(failed) => write!(f, "{} file(s) could not be read", failed),
+            Error::NoIndex(dir) => write!(f, "{} has no index, run `rust_demo index build {}` first", dir.display(), dir.display()),
+            Error::BadIndex(path) => write!(f, "{}: not a rust_demo index, build it again", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Args(err) => Some(err),
+            Error::Pattern(err) => Some(err),
+            Error::Literals(err) => Some(err),
+            Error::Io { source, .. } => Some(source),
+            Error::Walk(err) => Some(err),
+            Error::Watch(err) => Some(err),
+            Error::MissingArgs
+            | Error::UnknownEncoding(_)
+            | Error::Usage(_)
+            | Error::Query(_)
+            | Error::Unreadable(_)
+            | Error::NoIndex(_)
+            | Error::BadIndex(_) => None,
+        }
+    }
+}
+
+impl From<clap::Error> for Error {
+    fn from(err: clap::Error) -> Error {
+        Error::Args(err)
+    }
+}
+
+impl From<regex::Error> for Error {
+    fn from(err: regex::Error) -> Error {
+        Error::Pattern(err)
+    }
+}
+
+impl From<aho_corasick::BuildError> for Error {
+    fn from(err: aho_corasick::BuildError) -> Error {
+        Error::Literals(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(source: io::Error) -> Error {
+        Error::Io { path: None, source }
+    }
+}
+
+impl From<ignore::Error> for Error {
+    fn from(err: ignore::Error) -> Error {
+        Error::Walk(err)
+    }
+}
+
+impl From<notify::Error> for Error {
+    fn from(err: notify::Error) -> Error {
+        Error::Watch(err)
+    }
+}
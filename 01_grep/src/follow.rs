@@ -0,0 +1,252 @@
+use std::fs::{File, Metadata};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+use encoding_rs::{CoderResult, Decoder, Encoding};
+use notify::{RecursiveMode, Watcher};
+
+use crate::encoding;
+use crate::error::{self, Error};
+
+// how long to wait for a file system event before checking the file anyway,
+// some platforms and network drives do not report every write
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// how much is read from the file at a time
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// reads the lines appended to a file since the last poll, like `tail -f`
+pub struct Follower {
+    path: PathBuf,
+    file: Option<File>,
+    offset: u64,
+    // --encoding, otherwise the encoding is detected from the first bytes read
+    forced: Option<&'static Encoding>,
+    // `None` until there is something to detect the encoding from
+    decoder: Option<Decoder>,
+    // decoded text after the last newline, kept until the line is complete
+    pending: String,
+    // index of the next complete line
+    next_index: usize,
+}
+
+impl Follower {
+    // start following at the current end of the file, counting the lines
+    // already there so the numbering carries on from them
+    pub fn new(path: &Path, forced: Option<&'static Encoding>) -> io::Result<Follower> {
+        let file = File::open(path)?;
+        let mut follower = Follower { path: path.to_path_buf(), file: Some(file), offset: 0, forced, decoder: None, pending: String::new(), next_index: 0 };
+        follower.read_lines(|_, _| {})?;
+        Ok(follower)
+    }
+
+    // return the complete lines written since the last call; a truncated
+    // file is read again from the start, a rotated one (renamed or deleted
+    // and recreated) is drained and then reopened
+    pub fn poll(&mut self) -> io::Result<Vec<(usize, String)>> {
+        let mut lines = Vec::new();
+        let current = std::fs::metadata(&self.path).ok();
+
+        if let Some(file) = self.file.as_mut() {
+            let opened = file.metadata()?;
+            let rotated = current.as_ref().is_none_or(|current| !same_file(&opened, current));
+            if !rotated && opened.len() < self.offset {
+                // truncated in place
+                file.seek(SeekFrom::Start(0))?;
+                self.reset();
+            }
+            self.read_lines(|index, line| lines.push((index, line)))?;
+            if rotated {
+                // whatever was left in the old file is read, let it go
+                self.file = None;
+            }
+        }
+
+        if self.file.is_none() && current.is_some() {
+            match File::open(&self.path) {
+                Ok(file) => {
+                    self.file = Some(file);
+                    self.reset();
+                    self.read_lines(|index, line| lines.push((index, line)))?;
+                }
+                // it vanished again between the metadata call and the open
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(lines)
+    }
+
+    fn reset(&mut self) {
+        self.offset = 0;
+        // a new file may come in another encoding
+        self.decoder = None;
+        self.pending.clear();
+        self.next_index = 0;
+    }
+
+    // decode what was appended and pass each complete line to `on_line`
+    fn read_lines<F: FnMut(usize, String)>(&mut self, mut on_line: F) -> io::Result<()> {
+        let file = match self.file.as_mut() {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        let mut buf = vec![0; CHUNK_SIZE];
+        loop {
+            let read = file.read(&mut buf)?;
+            if read == 0 {
+                return Ok(());
+            }
+            self.offset += read as u64;
+            let mut chunk = &buf[..read];
+            let decoder = self.decoder.get_or_insert_with(|| match self.forced {
+                Some(encoding) => encoding.new_decoder_with_bom_removal(),
+                None => encoding::detect(chunk).new_decoder(),
+            });
+            // not the last input, a char cut off at the end of the chunk waits for the rest
+            loop {
+                self.pending.reserve(decoder.max_utf8_buffer_length(chunk.len()).unwrap_or(chunk.len()));
+                let (result, decoded, _) = decoder.decode_to_string(chunk, &mut self.pending, false);
+                chunk = &chunk[decoded..];
+                if result == CoderResult::InputEmpty {
+                    break;
+                }
+            }
+
+            let Some(end) = self.pending.rfind('\n') else {
+                continue;
+            };
+            let rest = self.pending.split_off(end + 1);
+            let done = std::mem::replace(&mut self.pending, rest);
+            for line in done[..end].split('\n') {
+                on_line(self.next_index, line.strip_suffix('\r').unwrap_or(line).to_string());
+                self.next_index += 1;
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+#[cfg(not(unix))]
+fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    // no stable file id here, a recreated file has a new creation time
+    match (a.created(), b.created()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => true,
+    }
+}
+
+// block forever, calling `on_line` for each line appended to `path`, decoded
+// from `forced` or the encoding detected from the file
+pub fn follow<F>(path: &Path, forced: Option<&'static Encoding>, mut on_line: F) -> error::Result<()>
+where
+    F: FnMut(usize, &str),
+{
+    let mut follower = Follower::new(path, forced).map_err(|err| Error::io(path, err))?;
+
+    // watch the directory rather than the file so rotation is noticed
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    watcher.watch(&dir, RecursiveMode::NonRecursive)?;
+
+    loop {
+        match rx.recv_timeout(POLL_INTERVAL) {
+            Ok(event) => {
+                event?;
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+        for (index, line) in follower.poll().map_err(|err| Error::io(path, err))? {
+            on_line(index, &line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    fn temp_file(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("rust_demo_follow_{}_{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn append(path: &Path, contents: &str) {
+        let mut file = OpenOptions::new().append(true).open(path).unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn test_follow_appended_lines() {
+        let path = temp_file("append", "old line\n");
+        let mut follower = Follower::new(&path, None).unwrap();
+        assert!(follower.poll().unwrap().is_empty());
+
+        append(&path, "first\nsec");
+        assert_eq!(follower.poll().unwrap(), vec![(1, "first".to_string())]);
+        append(&path, "ond\r\n");
+        assert_eq!(follower.poll().unwrap(), vec![(2, "second".to_string())]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_follow_truncate() {
+        let path = temp_file("truncate", "a long line before truncation\n");
+        let mut follower = Follower::new(&path, None).unwrap();
+        fs::write(&path, "new\n").unwrap();
+        assert_eq!(follower.poll().unwrap(), vec![(0, "new".to_string())]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_follow_rotate() {
+        let path = temp_file("rotate", "");
+        let rotated = path.with_extension("1");
+        let mut follower = Follower::new(&path, None).unwrap();
+        append(&path, "last of old\n");
+        fs::rename(&path, &rotated).unwrap();
+        fs::write(&path, "first of new\n").unwrap();
+        assert_eq!(
+            follower.poll().unwrap(),
+            vec![(0, "last of old".to_string()), (0, "first of new".to_string())]
+        );
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&rotated).unwrap();
+    }
+
+    #[test]
+    fn test_follow_encoding() {
+        let (gbk, _, _) = encoding_rs::GB18030.encode("【广东】苹果17\n");
+        let path = temp_file("gbk", "");
+        fs::write(&path, &gbk).unwrap();
+        let mut follower = Follower::new(&path, None).unwrap();
+        // a char split between two writes is decoded once it is complete
+        let (appended, _, _) = encoding_rs::GB18030.encode("【福建】iPhone 17\n");
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(&appended[..3]).unwrap();
+        assert!(follower.poll().unwrap().is_empty());
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(&appended[3..]).unwrap();
+        assert_eq!(follower.poll().unwrap(), vec![(1, "【福建】iPhone 17".to_string())]);
+
+        // an empty file has nothing to detect from, --encoding says what comes
+        fs::write(&path, "").unwrap();
+        let mut follower = Follower::new(&path, encoding::for_label("gbk")).unwrap();
+        OpenOptions::new().append(true).open(&path).unwrap().write_all(&appended).unwrap();
+        assert_eq!(follower.poll().unwrap(), vec![(0, "【福建】iPhone 17".to_string())]);
+        fs::remove_file(&path).unwrap();
+    }
+}
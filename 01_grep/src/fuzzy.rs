@@ -0,0 +1,109 @@
+use crate::matcher::{Hit, Matcher};
+
+// approximate matching: finds text that is at most `max_edits` insertions,
+// deletions or substitutions away from the query. Sellers' algorithm: one
+// column of the edit distance table per char of the line, so a line is
+// scanned once whatever the budget
+pub struct Fuzzy {
+    query: Vec<char>,
+    max_edits: usize,
+    case_sensitive: bool,
+}
+
+impl Fuzzy {
+    pub fn new(query: &str, max_edits: usize, case_sensitive: bool) -> Fuzzy {
+        let mut fuzzy = Fuzzy { query: Vec::new(), max_edits, case_sensitive };
+        fuzzy.query = query.chars().map(|c| fuzzy.fold(c)).collect();
+        fuzzy
+    }
+
+    fn fold(&self, c: char) -> char {
+        if self.case_sensitive {
+            c
+        } else {
+            c.to_lowercase().next().unwrap_or(c)
+        }
+    }
+}
+
+impl Matcher for Fuzzy {
+    // the match that ends first, extended while the next char brings it
+    // closer to the query, so `iPhon` does not win over `iPhone`
+    fn find_at(&self, line: &str, start: usize) -> Option<Hit> {
+        let m = self.query.len();
+        if m == 0 {
+            return Some(Hit { range: start..start, distance: 0 });
+        }
+        // a match needs at least one char right, or every text would match
+        let max_edits = self.max_edits.min(m - 1);
+
+        // distance[q]: fewest edits turning query[..q] into some text ending
+        // here, from[q]: byte offset where that text starts
+        let mut distance: Vec<usize> = (0..=m).collect();
+        let mut from = vec![start; m + 1];
+        let mut best: Option<Hit> = None;
+
+        for (i, c) in line[start..].char_indices() {
+            let next = start + i + c.len_utf8();
+            let c = self.fold(c);
+            let (mut diagonal, mut diagonal_from) = (distance[0], from[0]);
+            distance[0] = 0;
+            from[0] = next;
+            for q in 1..=m {
+                let (above, above_from) = (distance[q], from[q]);
+                // prefer a (mis)match, then an extra text char, then a missing one
+                let (mut d, mut f) = (diagonal + usize::from(self.query[q - 1] != c), diagonal_from);
+                if above + 1 < d {
+                    (d, f) = (above + 1, above_from);
+                }
+                if distance[q - 1] + 1 < d {
+                    (d, f) = (distance[q - 1] + 1, from[q - 1]);
+                }
+                (diagonal, diagonal_from) = (above, above_from);
+                distance[q] = d;
+                from[q] = f;
+            }
+
+            let d = distance[m];
+            match &best {
+                Some(hit) if d < hit.distance => best = Some(Hit { range: from[m]..next, distance: d }),
+                Some(_) => break,
+                None if d <= max_edits => best = Some(Hit { range: from[m]..next, distance: d }),
+                None => {}
+            }
+        }
+        best
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find(query: &str, max_edits: usize, line: &str) -> Option<(String, usize)> {
+        Fuzzy::new(query, max_edits, false).find_at(line, 0).map(|hit| (line[hit.range].to_string(), hit.distance))
+    }
+
+    #[test]
+    fn test_fuzzy_edits() {
+        assert_eq!(find("productCode", 1, "x%3FprodctCode%3D1"), Some(("prodctCode".into(), 1)));
+        assert_eq!(find("productCode", 1, "x%3FproductKode%3D1"), Some(("productKode".into(), 1)));
+        assert_eq!(find("productCode", 1, "x%3FproductCoode%3D1"), Some(("productCoode".into(), 1)));
+        assert_eq!(find("productCode", 1, "x%3FprodctKode%3D1"), None);
+        assert_eq!(find("productCode", 2, "x%3FprodctKode%3D1"), Some(("prodctKode".into(), 2)));
+    }
+
+    #[test]
+    fn test_fuzzy_prefers_closer_match() {
+        assert_eq!(find("iPhone", 1, "IPHONE 17"), Some(("IPHONE".into(), 0)));
+        assert_eq!(find("苹果17ProMax", 2, "【广东】苹果16promax单点"), Some(("苹果16promax".into(), 1)));
+        assert!(!Fuzzy::new("iPhone", 0, true).is_match("IPHONE"));
+    }
+
+    #[test]
+    fn test_fuzzy_budget_is_capped() {
+        // two edits of a two char query would match anything
+        assert_eq!(find("广东", 5, "福建"), None);
+        assert_eq!(find("广东", 5, "广西"), Some(("广".into(), 1)));
+    }
+}
@@ -0,0 +1,359 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, Metadata};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use rayon::prelude::*;
+use tempfile::NamedTempFile;
+
+use crate::casefold::is_cased;
+use crate::error::{self, Error};
+use crate::{encoding, walk, Config};
+
+// the index of a directory is kept in that directory
+pub const INDEX_FILE: &str = ".rust_demo_index";
+const MAGIC: &[u8; 8] = b"RDINDEX1";
+
+// what the index knows about one file
+struct Entry {
+    // relative to the indexed directory
+    path: String,
+    modified: (u64, u32),
+    size: u64,
+    // the file's trigrams, sorted, stored as varint deltas
+    trigrams: Vec<u8>,
+}
+
+impl Entry {
+    // a file that changed since it was indexed has to be searched whatever it held
+    fn is_fresh(&self, metadata: &Metadata) -> bool {
+        self.modified == stamp(metadata) && self.size == metadata.len()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Stats {
+    pub files: usize,
+    // files that were new or changed, the rest were taken from the old index
+    pub read: usize,
+    pub removed: usize,
+    // directory entries the walk could not read, each one was reported already
+    pub unreadable: usize,
+}
+
+// index every file under `root`, only reading those that changed since the last build
+pub fn build(root: &Path) -> error::Result<Stats> {
+    // an index that cannot be read is simply built again
+    let mut old: HashMap<String, Entry> = load(root).unwrap_or_default().into_iter().map(|e| (e.path.clone(), e)).collect();
+
+    let mut entries = Vec::new();
+    let mut changed = Vec::new();
+    let (files, unreadable) = walk::collect_files(root);
+    for file in files {
+        // files that cannot be named or stat'ed stay out, queries search them anyway
+        let (Some(path), Ok(metadata)) = (relative(root, &file), fs::metadata(&file)) else {
+            continue;
+        };
+        match old.remove(&path) {
+            Some(entry) if entry.is_fresh(&metadata) => entries.push(entry),
+            _ => changed.push((file, path, metadata)),
+        }
+    }
+    let removed = old.len();
+    let read: Vec<Entry> = changed
+        .into_par_iter()
+        .filter_map(|(file, path, metadata)| {
+            // binary files stay out like unreadable ones, a query with --binary searches them anyway
+            if encoding::is_binary_file(&file, None).unwrap_or(true) {
+                return None;
+            }
+            let trigrams = encode(&file_trigrams(&file).ok()?);
+            Some(Entry { path, modified: stamp(&metadata), size: metadata.len(), trigrams })
+        })
+        .collect();
+    let stats = Stats { files: entries.len() + read.len(), read: read.len(), removed, unreadable };
+
+    entries.extend(read);
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    save(root, &entries).map_err(|err| Error::io(&root.join(INDEX_FILE), err))?;
+    Ok(stats)
+}
+
+// the files under `root` that can match the patterns of `config`: every
+// indexed file that has all trigrams of one of the patterns, and every file
+// the index does not know about or that changed since it was built; with
+// how many entries the walk could not read, like `walk::collect_files`
+pub fn candidates(root: &Path, config: &Config, patterns: &[String]) -> error::Result<(Vec<PathBuf>, usize)> {
+    let entries = load(root)?;
+    let entries: HashMap<&str, &Entry> = entries.iter().map(|e| (e.path.as_str(), e)).collect();
+    // `None` when some pattern gives nothing to look up, then every file may match;
+    // -v, -L and -c print something for the files without a match too
+    let every_file = config.invert || config.files_without_match || config.count;
+    let wanted: Option<Vec<Vec<u32>>> = if every_file || config.regex || config.multiline || config.normalize || config.fuzzy.is_some() || config.query.is_some() {
+        None
+    } else {
+        patterns.iter().map(|p| query_trigrams(p, config.case_sensitive)).collect()
+    };
+
+    let mut found = Vec::new();
+    let (files, unreadable) = walk::collect_files(root);
+    for file in files {
+        let entry = relative(root, &file).and_then(|path| entries.get(path.as_str()).copied());
+        let fresh = entry.filter(|e| fs::metadata(&file).is_ok_and(|m| e.is_fresh(&m)));
+        let keep = match (fresh, &wanted) {
+            (Some(entry), Some(wanted)) => wanted.iter().any(|trigrams| contains_all(&entry.trigrams, trigrams)),
+            _ => true,
+        };
+        if keep {
+            found.push(file);
+        }
+    }
+    Ok((found, unreadable))
+}
+
+fn relative(root: &Path, file: &Path) -> Option<String> {
+    file.strip_prefix(root).ok()?.to_str().map(String::from)
+}
+
+fn stamp(metadata: &Metadata) -> (u64, u32) {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or((0, 0), |since| (since.as_secs(), since.subsec_nanos()))
+}
+
+// trigrams of the decoded text, ASCII letters lowercased so one index serves
+// case-sensitive and case-insensitive queries. The text is read a buffer at a
+// time and the trigrams kept in a set, so a big file needs no more memory
+// than the distinct trigrams in it.
+fn file_trigrams(path: &Path) -> io::Result<Vec<u32>> {
+    let mut reader = encoding::decode_reader(fs::File::open(path)?, None)?;
+    let mut seen = HashSet::new();
+    // the text of a buffer after the last two bytes of the one before, a
+    // trigram can cross from one to the next
+    let mut window = Vec::new();
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+        let read = buf.len();
+        window.extend_from_slice(buf);
+        reader.consume(read);
+        window.make_ascii_lowercase();
+        seen.extend(window.windows(3).map(trigram));
+        window.drain(..window.len().saturating_sub(2));
+    }
+    let mut trigrams: Vec<u32> = seen.into_iter().collect();
+    trigrams.sort_unstable();
+    Ok(trigrams)
+}
+
+fn trigram(bytes: &[u8]) -> u32 {
+    u32::from(bytes[0]) << 16 | u32::from(bytes[1]) << 8 | u32::from(bytes[2])
+}
+
+// the trigrams every match of a literal contains, `None` if there are none.
+// Ignoring case, letters outside ASCII have other bytes in other cases, and
+// `k` / `i` also match the Kelvin sign and `İ`, so only runs of the other
+// chars count.
+fn query_trigrams(pattern: &str, case_sensitive: bool) -> Option<Vec<u32>> {
+    let safe = |c: char| case_sensitive || !(matches!(c, 'k' | 'K' | 'i' | 'I') || !c.is_ascii() && is_cased(c));
+    let mut trigrams = Vec::new();
+    for run in pattern.split(|c: char| !safe(c)) {
+        let run = run.to_ascii_lowercase();
+        trigrams.extend(run.as_bytes().windows(3).map(trigram));
+    }
+    trigrams.sort_unstable();
+    trigrams.dedup();
+    (!trigrams.is_empty()).then_some(trigrams)
+}
+
+// whether the encoded `trigrams` hold every one of the sorted `wanted`
+fn contains_all(trigrams: &[u8], wanted: &[u32]) -> bool {
+    let mut pos = 0;
+    let mut current: Option<u32> = None;
+    for want in wanted.iter() {
+        while current.is_none_or(|current| current < *want) {
+            let Some(delta) = read_varint(trigrams, &mut pos) else {
+                return false;
+            };
+            current = Some(current.unwrap_or(0) + delta as u32);
+        }
+        if current != Some(*want) {
+            return false;
+        }
+    }
+    true
+}
+
+fn encode(trigrams: &[u32]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(trigrams.len() * 2);
+    let mut last = 0;
+    for trigram in trigrams.iter() {
+        write_varint(&mut out, u64::from(trigram - last));
+        last = *trigram;
+    }
+    out
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(bytes: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = *bytes.get(*pos)?;
+        *pos += 1;
+        value |= u64::from(byte & 0x7f) << shift;
+        if byte < 0x80 {
+            return Some(value);
+        }
+    }
+    None
+}
+
+fn save(root: &Path, entries: &[Entry]) -> io::Result<()> {
+    let mut out = MAGIC.to_vec();
+    write_varint(&mut out, entries.len() as u64);
+    for entry in entries.iter() {
+        write_varint(&mut out, entry.path.len() as u64);
+        out.extend_from_slice(entry.path.as_bytes());
+        write_varint(&mut out, entry.modified.0);
+        write_varint(&mut out, u64::from(entry.modified.1));
+        write_varint(&mut out, entry.size);
+        write_varint(&mut out, entry.trigrams.len() as u64);
+        out.extend_from_slice(&entry.trigrams);
+    }
+    // a query running meanwhile sees the old index or the new one, never half of one
+    let mut temp = NamedTempFile::new_in(root)?;
+    temp.write_all(&out)?;
+    temp.persist(root.join(INDEX_FILE)).map_err(|err| err.error)?;
+    Ok(())
+}
+
+fn load(root: &Path) -> error::Result<Vec<Entry>> {
+    let path = root.join(INDEX_FILE);
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Err(Error::NoIndex(root.to_path_buf())),
+        Err(err) => return Err(Error::io(&path, err)),
+    };
+    parse(&bytes).ok_or(Error::BadIndex(path))
+}
+
+fn parse(bytes: &[u8]) -> Option<Vec<Entry>> {
+    let mut pos = MAGIC.len();
+    if bytes.get(..pos)? != MAGIC {
+        return None;
+    }
+    let take = |pos: &mut usize, len: u64| -> Option<&[u8]> {
+        let slice = bytes.get(*pos..*pos + usize::try_from(len).ok()?)?;
+        *pos += slice.len();
+        Some(slice)
+    };
+    let count = read_varint(bytes, &mut pos)?;
+    let mut entries = Vec::new();
+    for _ in 0..count {
+        let len = read_varint(bytes, &mut pos)?;
+        let path = String::from_utf8(take(&mut pos, len)?.to_vec()).ok()?;
+        let modified = (read_varint(bytes, &mut pos)?, u32::try_from(read_varint(bytes, &mut pos)?).ok()?);
+        let size = read_varint(bytes, &mut pos)?;
+        let len = read_varint(bytes, &mut pos)?;
+        let trigrams = take(&mut pos, len)?.to_vec();
+        entries.push(Entry { path, modified, size, trigrams });
+    }
+    Some(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_trigrams() {
+        assert_eq!(query_trigrams("abcd", true), Some(vec![trigram(b"abc"), trigram(b"bcd")]));
+        // indexed text is ASCII lowercased, so the query is too
+        assert_eq!(query_trigrams("ABC", true), Some(vec![trigram(b"abc")]));
+        assert_eq!(query_trigrams("ab", true), None);
+        // ignoring case, `k` might be a Kelvin sign and Cyrillic has other bytes in other cases
+        assert_eq!(query_trigrams("Kab", false), None);
+        assert_eq!(query_trigrams("про", false), None);
+        assert_eq!(query_trigrams("广东", false).map(|t| t.len()), Some(4));
+    }
+
+    #[test]
+    fn test_contains_all() {
+        let encoded = encode(&[0, 5, 300, 70_000]);
+        assert!(contains_all(&encoded, &[0, 300]));
+        assert!(contains_all(&encoded, &[5, 70_000]));
+        assert!(contains_all(&encoded, &[]));
+        assert!(!contains_all(&encoded, &[6]));
+        assert!(!contains_all(&encoded, &[70_001]));
+        assert!(!contains_all(&encode(&[5]), &[0]));
+    }
+
+    #[test]
+    fn test_build_is_incremental() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("gd.txt"), "【广东】苹果17promax\n").unwrap();
+        fs::write(root.join("fj.txt"), "【福建】iPhone 17 Pro Max\n").unwrap();
+        fs::write(root.join("old.txt"), "nothing\n").unwrap();
+        assert_eq!(build(root).unwrap(), Stats { files: 3, read: 3, removed: 0, unreadable: 0 });
+
+        fs::remove_file(root.join("old.txt")).unwrap();
+        fs::write(root.join("gd.txt"), "【广东】苹果17promax 单点\n").unwrap();
+        assert_eq!(build(root).unwrap(), Stats { files: 2, read: 1, removed: 1, unreadable: 0 });
+
+        // binary files are not indexed
+        fs::write(root.join("logo.png"), b"\x89PNG\r\n\x1a\n\x00\x00promax").unwrap();
+        assert_eq!(build(root).unwrap(), Stats { files: 2, read: 0, removed: 0, unreadable: 0 });
+
+        let entries = load(root).unwrap();
+        let gd = entries.iter().find(|e| e.path == "gd.txt").unwrap();
+        assert!(contains_all(&gd.trigrams, &query_trigrams("单点", true).unwrap()));
+        assert!(!contains_all(&gd.trigrams, &query_trigrams("iphone", false).unwrap()));
+    }
+
+    #[test]
+    fn test_file_trigrams_across_buffers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("big.txt");
+        // "Pro" straddles the first 64 KiB buffer of the reader
+        let mut text = "x".repeat(64 * 1024 - 2);
+        text.push_str("Pro Max\n");
+        fs::write(&path, &text).unwrap();
+        let trigrams = file_trigrams(&path).unwrap();
+        assert_eq!(trigrams, query_trigrams("xxxpro max\n", true).unwrap());
+        assert!(trigrams.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_candidates_keep_files_without_match() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        fs::write(root.join("a.txt"), "apple pie
+").unwrap();
+        fs::write(root.join("b.txt"), "banana
+").unwrap();
+        build(root).unwrap();
+        let names = |args: &[&str]| {
+            let config = Config::new(&crate::tests::argv(args)).unwrap();
+            let (files, _) = candidates(root, &config, &config.patterns).unwrap();
+            files.iter().map(|f| relative(root, f).unwrap()).collect::<Vec<_>>()
+        };
+        assert_eq!(names(&["program_name", "apple"]), vec!["a.txt"]);
+        // the files the index rules out are the ones these print
+        assert_eq!(names(&["program_name", "-v", "apple"]), vec!["a.txt", "b.txt"]);
+        assert_eq!(names(&["program_name", "-L", "apple"]), vec!["a.txt", "b.txt"]);
+        assert_eq!(names(&["program_name", "-c", "apple"]), vec!["a.txt", "b.txt"]);
+    }
+}
@@ -0,0 +1,398 @@
+use std::env;
+use std::io::{self, BufRead, IsTerminal, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor};
+use crossterm::terminal::{self, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute, queue};
+
+use crate::error::{self, Error};
+use crate::matcher::PatternSet;
+use crate::urls::{char_width, display_width};
+use crate::{display_name, open_input, Config, STDIN_PATH};
+
+// more matching lines than this are not listed, the query needs narrowing anyway
+const MAX_ROWS: usize = 10_000;
+// the query line and the status line
+const HEADER_LINES: u16 = 2;
+
+// --interactive: search the files again on every keystroke, then print the
+// chosen line or open it in $EDITOR. The screen is drawn on stderr so the
+// chosen line can be piped on.
+pub fn run(config: Config, files: &[(PathBuf, bool)], with_path: bool) -> error::Result<bool> {
+    if !io::stderr().is_terminal() {
+        return Err(Error::Usage("--interactive needs a terminal"));
+    }
+    // stdin can only be read once, so every file is read up front
+    let mut loaded = Vec::new();
+    for (file, walked) in files.iter() {
+        match read_lines(&config, file) {
+            Ok(lines) => loaded.push(Loaded { path: file.clone(), lines }),
+            Err(err) if *walked => eprintln!("{}: {}", file.display(), err),
+            Err(err) => return Err(Error::io(file, err)),
+        }
+    }
+    let mut state = State::new(config, loaded, with_path);
+
+    let chosen = {
+        let _screen = Screen::enter()?;
+        let mut out = io::stderr();
+        loop {
+            let (width, height) = terminal::size()?;
+            let page = usize::from(height.saturating_sub(HEADER_LINES).max(1));
+            state.scroll(page);
+            draw(&mut out, &state, usize::from(width), page)?;
+            // a resize only needs the redraw
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match state.key(key, page) {
+                Step::Continue => {}
+                Step::Quit => break None,
+                Step::Print => break Some(state.selected),
+                Step::Edit => edit(&mut out, &mut state)?,
+            }
+        }
+    };
+
+    let Some(row) = chosen.and_then(|selected| state.rows.get(selected)) else {
+        return Ok(false);
+    };
+    let file = &state.files[row.file];
+    let path = if state.with_path { Some(display_name(&file.path)) } else { None };
+    print!("{}", state.config.format_line(path, row.line, &file.lines[row.line], true, None, &row.ranges));
+    Ok(true)
+}
+
+// a file read into memory once
+struct Loaded {
+    path: PathBuf,
+    lines: Vec<String>,
+}
+
+// a selected line and the byte ranges of its matches
+struct Row {
+    // index in `State::files`
+    file: usize,
+    line: usize,
+    ranges: Vec<Range<usize>>,
+}
+
+// what a key asks for, besides changing the state
+#[derive(Debug, PartialEq)]
+enum Step {
+    Continue,
+    Quit,
+    // print the selected row and quit
+    Print,
+    // open the selected row in $EDITOR, then carry on
+    Edit,
+}
+
+// everything on screen, kept apart from the terminal so keys can be tested
+struct State {
+    // the -s / -E / -n toggles flip its flags, the query is its only pattern
+    config: Config,
+    files: Vec<Loaded>,
+    with_path: bool,
+    query: String,
+    rows: Vec<Row>,
+    // why there are no rows, like a regex that does not compile yet
+    message: Option<String>,
+    selected: usize,
+    // the first row on screen
+    top: usize,
+}
+
+impl State {
+    fn new(config: Config, files: Vec<Loaded>, with_path: bool) -> State {
+        let query = config.patterns.first().cloned().unwrap_or_default();
+        let mut state = State { config, files, with_path, query, rows: Vec::new(), message: None, selected: 0, top: 0 };
+        state.search();
+        state
+    }
+
+    fn search(&mut self) {
+        self.rows.clear();
+        self.message = None;
+        self.selected = 0;
+        self.top = 0;
+        if self.query.is_empty() {
+            return;
+        }
+        self.config.patterns = vec![self.query.clone()];
+        let matcher = match PatternSet::new(&self.config) {
+            Ok(matcher) => matcher,
+            // a half typed regex: the last line of the error says what is missing
+            Err(err) => {
+                self.message = err.to_string().lines().last().map(String::from);
+                return;
+            }
+        };
+        for (f, file) in self.files.iter().enumerate() {
+            let mut found = 0;
+            for (line, text) in file.lines.iter().enumerate() {
+                if self.rows.len() >= MAX_ROWS || self.config.max_count.is_some_and(|max| found >= max) {
+                    break;
+                }
+                let spans = matcher.find_spans(text);
+                if spans.is_empty() != self.config.invert {
+                    continue;
+                }
+                found += 1;
+                self.rows.push(Row { file: f, line, ranges: spans.into_iter().map(|span| span.range).collect() });
+            }
+        }
+    }
+
+    fn key(&mut self, key: KeyEvent, page: usize) -> Step {
+        let last = self.rows.len().saturating_sub(1);
+        let control = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => return Step::Quit,
+            KeyCode::Char('c') if control => return Step::Quit,
+            KeyCode::Enter if !self.rows.is_empty() => return Step::Print,
+            KeyCode::Char('o') if control && !self.rows.is_empty() => return Step::Edit,
+            // the toggles use the letters of their flags
+            KeyCode::Char('s') if control => {
+                self.config.case_sensitive = !self.config.case_sensitive;
+                self.search();
+            }
+            KeyCode::Char('e') if control => {
+                self.config.regex = !self.config.regex;
+                self.search();
+            }
+            KeyCode::Char('n') if control => self.config.show_line_number = !self.config.show_line_number,
+            KeyCode::Char('u') if control => {
+                self.query.clear();
+                self.search();
+            }
+            KeyCode::Char(c) if !control => {
+                self.query.push(c);
+                self.search();
+            }
+            KeyCode::Backspace if !self.query.is_empty() => {
+                self.query.pop();
+                self.search();
+            }
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down => self.selected = (self.selected + 1).min(last),
+            KeyCode::PageUp => self.selected = self.selected.saturating_sub(page),
+            KeyCode::PageDown => self.selected = (self.selected + page).min(last),
+            _ => {}
+        }
+        Step::Continue
+    }
+
+    // keep the selected row on a screen of `page` rows
+    fn scroll(&mut self, page: usize) {
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if self.selected >= self.top + page {
+            self.top = self.selected + 1 - page;
+        }
+    }
+
+    fn status(&self) -> String {
+        let found = match &self.message {
+            Some(message) => message.clone(),
+            None if self.rows.len() >= MAX_ROWS => format!("{}+ lines", MAX_ROWS),
+            None => format!("{} lines", self.rows.len()),
+        };
+        let on = |flag: bool| if flag { "on" } else { "off" };
+        format!(
+            "{}  ^S case {}  ^E regex {}  ^N numbers {}  Enter print  ^O edit  Esc quit",
+            found,
+            on(self.config.case_sensitive),
+            on(self.config.regex),
+            on(self.config.show_line_number)
+        )
+    }
+}
+
+fn read_lines(config: &Config, file: &Path) -> io::Result<Vec<String>> {
+    open_input(config, file)?.lines().collect()
+}
+
+// raw mode on the alternate screen, given back even when drawing fails
+struct Screen;
+
+impl Screen {
+    fn enter() -> io::Result<Screen> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stderr(), EnterAlternateScreen)?;
+        Ok(Screen)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(io::stderr(), LeaveAlternateScreen, cursor::Show);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn draw<W: Write>(out: &mut W, state: &State, width: usize, page: usize) -> io::Result<()> {
+    queue!(out, cursor::Hide, terminal::Clear(ClearType::All), cursor::MoveTo(0, 0))?;
+    print_clipped(out, &format!("> {}", state.query), width)?;
+    queue!(out, cursor::MoveTo(0, 1), SetAttribute(Attribute::Dim))?;
+    print_clipped(out, &state.status(), width)?;
+    queue!(out, SetAttribute(Attribute::Reset))?;
+
+    for (i, row) in state.rows.iter().enumerate().skip(state.top).take(page) {
+        queue!(out, cursor::MoveTo(0, HEADER_LINES + (i - state.top) as u16))?;
+        draw_row(out, state, row, i == state.selected, width)?;
+    }
+    let column = (display_width(&state.query) + 2).min(width.saturating_sub(1));
+    queue!(out, cursor::MoveTo(column as u16, 0), cursor::Show)?;
+    out.flush()
+}
+
+// `path:line:` like the normal output, with the matches in bold red
+fn draw_row<W: Write>(out: &mut W, state: &State, row: &Row, selected: bool, width: usize) -> io::Result<()> {
+    let file = &state.files[row.file];
+    let text = &file.lines[row.line];
+    let mut prefix = String::from(if selected { "> " } else { "  " });
+    if state.with_path {
+        prefix.push_str(&format!("{}:", display_name(&file.path).display()));
+    }
+    if state.config.show_line_number {
+        prefix.push_str(&format!("{}:", row.line + 1));
+    }
+    if selected {
+        queue!(out, SetAttribute(Attribute::Bold))?;
+    }
+    let mut left = print_clipped(out, &prefix, width)?;
+    queue!(out, SetAttribute(Attribute::Reset))?;
+
+    let mut pos = 0;
+    for range in row.ranges.iter() {
+        left = print_clipped(out, &text[pos..range.start], left)?;
+        queue!(out, SetForegroundColor(Color::Red), SetAttribute(Attribute::Bold))?;
+        left = print_clipped(out, &text[range.clone()], left)?;
+        queue!(out, ResetColor, SetAttribute(Attribute::Reset))?;
+        pos = range.end;
+    }
+    print_clipped(out, &text[pos..], left)?;
+    Ok(())
+}
+
+// print as much of `text` as fits in `columns`, returning the columns left;
+// tabs and other control chars would move the cursor, they are shown as spaces
+fn print_clipped<W: Write>(out: &mut W, text: &str, columns: usize) -> io::Result<usize> {
+    let mut left = columns;
+    let mut end = 0;
+    for (i, c) in text.char_indices() {
+        let width = char_width(c);
+        if width > left {
+            break;
+        }
+        left -= width;
+        end = i + c.len_utf8();
+    }
+    queue!(out, Print(text[..end].replace(|c: char| c.is_control(), " ")))?;
+    Ok(left)
+}
+
+// hand the terminal to the editor at the selected line, then read the file
+// again, it probably changed
+fn edit<W: Write>(out: &mut W, state: &mut State) -> io::Result<()> {
+    let row = &state.rows[state.selected];
+    let (file, line) = (row.file, row.line);
+    let path = state.files[file].path.clone();
+    if path == Path::new(STDIN_PATH) {
+        state.message = Some(String::from("standard input cannot be edited"));
+        return Ok(());
+    }
+    // `EDITOR="code -w"` is a command with arguments
+    let editor = env::var("VISUAL").or_else(|_| env::var("EDITOR")).unwrap_or_else(|_| String::from("vi"));
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+
+    execute!(out, LeaveAlternateScreen, cursor::Show)?;
+    terminal::disable_raw_mode()?;
+    let status = Command::new(program).args(words).arg(format!("+{}", line + 1)).arg(&path).status();
+    terminal::enable_raw_mode()?;
+    execute!(out, EnterAlternateScreen)?;
+
+    if let Err(err) = status {
+        state.message = Some(format!("{}: {}", program, err));
+        return Ok(());
+    }
+    if let Ok(lines) = read_lines(&state.config, &path) {
+        state.files[file].lines = lines;
+    }
+    state.search();
+    // stay near the edited line
+    state.selected = state.rows.iter().position(|r| r.file == file && r.line >= line).unwrap_or(0);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(lines: &[&str]) -> State {
+        let lines = lines.iter().map(|line| line.to_string()).collect();
+        State::new(Config::default(), vec![Loaded { path: PathBuf::from("a.txt"), lines }], false)
+    }
+
+    fn press(state: &mut State, code: KeyCode, modifiers: KeyModifiers) -> Step {
+        state.key(KeyEvent::new(code, modifiers), 10)
+    }
+
+    fn type_text(state: &mut State, text: &str) {
+        for c in text.chars() {
+            press(state, KeyCode::Char(c), KeyModifiers::NONE);
+        }
+    }
+
+    // (line, [(start, end)]) of every row
+    fn found(state: &State) -> Vec<(usize, Vec<(usize, usize)>)> {
+        state.rows.iter().map(|row| (row.line, row.ranges.iter().map(|r| (r.start, r.end)).collect())).collect()
+    }
+
+    #[test]
+    fn test_every_key_searches_again() {
+        let mut state = state(&["【广东】苹果17promax", "iPhone 17 Pro Max", "苹果16"]);
+        assert!(state.rows.is_empty());
+        type_text(&mut state, "苹果");
+        assert_eq!(found(&state), vec![(0, vec![(12, 18)]), (2, vec![(0, 6)])]);
+        type_text(&mut state, "17");
+        assert_eq!(found(&state), vec![(0, vec![(12, 20)])]);
+        press(&mut state, KeyCode::Backspace, KeyModifiers::NONE);
+        assert_eq!(state.rows.len(), 2);
+
+        press(&mut state, KeyCode::Down, KeyModifiers::NONE);
+        press(&mut state, KeyCode::Down, KeyModifiers::NONE);
+        assert_eq!(state.selected, 1);
+        assert_eq!(press(&mut state, KeyCode::Enter, KeyModifiers::NONE), Step::Print);
+        assert_eq!(press(&mut state, KeyCode::Esc, KeyModifiers::NONE), Step::Quit);
+    }
+
+    #[test]
+    fn test_toggles() {
+        let mut state = state(&["iPhone 17 Pro Max", "iphone 16"]);
+        type_text(&mut state, "iPhone");
+        assert_eq!(state.rows.len(), 2);
+        press(&mut state, KeyCode::Char('s'), KeyModifiers::CONTROL);
+        assert_eq!(state.rows.len(), 1);
+
+        press(&mut state, KeyCode::Char('u'), KeyModifiers::CONTROL);
+        press(&mut state, KeyCode::Char('e'), KeyModifiers::CONTROL);
+        type_text(&mut state, "1[");
+        assert!(state.rows.is_empty() && state.message.is_some());
+        type_text(&mut state, "67]");
+        assert_eq!(found(&state), vec![(0, vec![(7, 9)]), (1, vec![(7, 9)])]);
+
+        press(&mut state, KeyCode::Char('n'), KeyModifiers::CONTROL);
+        assert!(state.config.show_line_number);
+        assert!(state.status().contains("^N numbers on"));
+    }
+}
@@ -0,0 +1,105 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::matcher::Span;
+use crate::urls::Link;
+
+// one JSON Lines record per match
+#[derive(Serialize)]
+struct MatchRecord<'a> {
+    path: Option<String>,
+    // 1-based line number
+    line: usize,
+    // -U: the line the match ends on, when that is a later one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end_line: Option<usize>,
+    // 1-based column, counted in chars so CJK text lines up in editors
+    column: usize,
+    // byte offset of the match from the start of the (decoded) input
+    offset: usize,
+    text: &'a str,
+    line_text: &'a str,
+    // which pattern matched, 0-based, -e patterns before -f file lines
+    pattern: usize,
+    submatches: Vec<Option<SubmatchRecord<'a>>>,
+    // --section: the heading the match is under
+    #[serde(skip_serializing_if = "Option::is_none")]
+    section: Option<&'a str>,
+    // --fuzzy: edits between the pattern and `text`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    distance: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct SubmatchRecord<'a> {
+    group: usize,
+    name: Option<&'a str>,
+    text: &'a str,
+    // byte range within the line
+    start: usize,
+    end: usize,
+}
+
+// `index` is the 0-based line number, `line_offset` where the line starts;
+// with -U `line` is every line a match covers
+pub fn write_matches<W: Write>(
+    out: &mut W,
+    path: Option<&Path>,
+    index: usize,
+    line_offset: usize,
+    line: &str,
+    section: Option<&str>,
+    spans: &[Span],
+) -> io::Result<()> {
+    for span in spans.iter() {
+        let text = &line[span.range.clone()];
+        // a newline that ends the match is still on its last line
+        let newlines = text.strip_suffix('\n').unwrap_or(text).matches('\n').count();
+        let record = MatchRecord {
+            path: path.map(|p| p.display().to_string()),
+            line: index + 1,
+            end_line: (newlines > 0).then_some(index + 1 + newlines),
+            column: line[..span.range.start].chars().count() + 1,
+            offset: line_offset + span.range.start,
+            text,
+            line_text: line,
+            pattern: span.pattern,
+            submatches: span.submatches.iter().enumerate().map(|(i, sub)| {
+                sub.as_ref().map(|sub| SubmatchRecord {
+                    group: i + 1,
+                    name: sub.name.as_deref(),
+                    text: &line[sub.range.clone()],
+                    start: sub.range.start,
+                    end: sub.range.end,
+                })
+            }).collect(),
+            section,
+            distance: span.distance,
+        };
+        serde_json::to_writer(&mut *out, &record)?;
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+// --urls: one record per link
+#[derive(Serialize)]
+struct LinkRecord<'a> {
+    path: Option<String>,
+    line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    section: Option<&'a str>,
+    #[serde(flatten)]
+    link: &'a Link,
+}
+
+pub fn write_links<W: Write>(out: &mut W, path: Option<&Path>, index: usize, section: Option<&str>, links: &[Link]) -> io::Result<()> {
+    for link in links.iter() {
+        let record = LinkRecord { path: path.map(|p| p.display().to_string()), line: index + 1, section, link };
+        serde_json::to_writer(&mut *out, &record)?;
+        out.write_all(b"\n")?;
+    }
+    Ok(())
+}
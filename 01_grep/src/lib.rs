@@ -1,62 +1,555 @@
-pub fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
-    // read file
-    let contents = std::fs::read_to_string(config.path)?;
+use std::fs::File;
+use std::io::{self, BufRead, Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
 
-    let result  = if config.case_sensitive {
-        Config::search_case_sensitive(&config.target, &contents)
+use clap::Parser;
+use encoding_rs::Encoding;
+use rayon::prelude::*;
+use regex::{Regex, RegexBuilder};
+
+use casefold::CaseInsensitive;
+use matcher::PatternSet;
+
+pub use error::Error;
+pub use matcher::Matcher;
+pub use searcher::{Match, Searcher, Sink};
+
+pub mod casefold;
+pub mod cli;
+pub mod color;
+pub mod context;
+pub mod dates;
+pub mod encoding;
+pub mod error;
+pub mod follow;
+pub mod fuzzy;
+pub mod index;
+pub mod interactive;
+pub mod json;
+pub mod matcher;
+pub mod multiline;
+pub mod normalize;
+pub mod ordered;
+pub mod query;
+pub mod replace;
+pub mod scope;
+pub mod search;
+pub mod searcher;
+pub mod urls;
+pub mod walk;
+
+// the path that means "read standard input", also used when no path is given
+const STDIN_PATH: &str = "-";
+const STDIN_LABEL: &str = "(standard input)";
+
+// returns whether anything was selected, for grep style exit codes
+pub fn run(mut config: Config) -> error::Result<bool> {
+    config.color = config.color.resolve();
+    if config.action == Action::IndexBuild {
+        return build_indexes(&config);
+    }
+    let matcher = PatternSet::new(&config)?;
+
+    // (file, came from a directory walk)
+    let mut files: Vec<(PathBuf, bool)> = Vec::new();
+    // directory entries the walks could not read
+    let mut unreadable = 0;
+    for path in config.paths.iter() {
+        let root = Path::new(path);
+        let walked = root.is_dir();
+        // files named on the command line are always searched, only walked ones
+        // are filtered by name, type and age; one without a readable mtime is left to fail later
+        let (found, failed) = if walked && config.action == Action::IndexQuery {
+            index::candidates(root, &config, matcher.patterns())?
+        } else {
+            walk::collect_files(root)
+        };
+        unreadable += failed;
+        let filter = walk::Filter::new(root, &config.globs, &config.types)?;
+        let files_of_root = found
+            .into_iter()
+            .filter(|f| !walked || filter.allows(f))
+            .filter(|f| !walked || config.modified.is_unbounded() || dates::modified(f).map_or(true, |t| config.modified.contains(t)));
+        files.extend(files_of_root.map(|f| (f, walked)));
+    }
+    // walked files that look binary, like the images of a web tree, are
+    // skipped without --binary; one that cannot be read is left to fail later
+    if !config.binary {
+        files = files
+            .into_par_iter()
+            .filter(|(f, walked)| !walked || !encoding::is_binary_file(f, config.encoding).unwrap_or(false))
+            .collect();
+    }
+    // an index query names its files even when only one can match
+    let with_path = files.len() > 1 || config.action == Action::IndexQuery;
+
+    if config.follow && (files.len() != 1 || Path::new(&config.paths[0]).is_dir() || config.paths[0] == STDIN_PATH) {
+        return Err(Error::Usage("--follow needs exactly one file"));
+    }
+
+    let result = if config.interactive {
+        interactive::run(config, &files, with_path)
+    } else if let Some(template) = &config.replace {
+        replace_files(&config, &matcher, template, &files)
+    } else if config.rank {
+        rank_files(&config, &matcher, &files, with_path)
     } else {
-        Config::search_case_insensitive(&config.target, &contents)
+        search_files(&config, &matcher, &files, with_path)
     };
+    // the entries a walk could not read were reported already, like a file
+    // that cannot be read they fail the run once the rest is searched
+    match result {
+        Ok(_) if unreadable > 0 => Err(Error::Unreadable(unreadable)),
+        Err(Error::Unreadable(failed)) => Err(Error::Unreadable(failed + unreadable)),
+        result => result,
+    }
+}
 
-    for (_, line) in result.iter().enumerate() {
-        if config.show_line_number {
-            println!("{}: {}", line.0 +1, line.1);
-        } else {
-            println!("{}", line.1);
+// search and print every file, then keep following the one file with --follow
+fn search_files(config: &Config, matcher: &PatternSet, files: &[(PathBuf, bool)], with_path: bool) -> error::Result<bool> {
+    // a single input is streamed straight to stdout
+    let mut selected = false;
+    if let [(file, _)] = files {
+        let stdout = io::stdout();
+        selected = search_file(config, matcher, file, with_path, &mut stdout.lock()).map_err(|err| Error::io(file, err))?;
+    } else {
+        // search every file in parallel, printing each one's output in the input order
+        let with_context = !config.json
+            && !config.only_matching
+            && (config.before_context > 0 || config.after_context > 0 || config.scope != scope::Scope::Line);
+        let mut printed = false;
+        let mut failed = 0;
+        let stdout = io::stdout();
+        let mut stdout = stdout.lock();
+        ordered::for_each(
+            files,
+            |(file, _)| {
+                let mut output = Vec::new();
+                search_file(config, matcher, file, with_path, &mut output).map(|selected| (output, selected))
+            },
+            |(file, _), result| {
+                match result {
+                    Ok((output, file_selected)) => {
+                        if with_context && printed && !output.is_empty() {
+                            writeln!(stdout, "{}", config.paint(color::SEPARATOR, "--"))?;
+                        }
+                        printed |= !output.is_empty();
+                        selected |= file_selected;
+                        stdout.write_all(&output)?;
+                    }
+                    // like grep, one unreadable file does not stop the others
+                    Err(err) => {
+                        eprintln!("{}: {}", file.display(), err);
+                        failed += 1;
+                    }
+                }
+                Ok::<(), io::Error>(())
+            },
+        )?;
+        // like grep, a read error wins over the match result
+        if failed > 0 {
+            return Err(Error::Unreadable(failed));
+        }
+    }
+
+    if config.follow {
+        follow::follow(&files[0].0, config.encoding, |index, line| {
+            if matcher.is_match(line) != config.invert {
+                print!("{}", config.format_line(None, index, line, true, None, &config.highlights(matcher, line)));
+            }
+        })?;
+    }
+    Ok(selected)
+}
+
+// `index build`: create or update the index of every path
+fn build_indexes(config: &Config) -> error::Result<bool> {
+    let mut unreadable = 0;
+    for path in config.paths.iter() {
+        let root = Path::new(path);
+        if !root.is_dir() {
+            return Err(Error::Usage("index build needs a directory"));
+        }
+        let stats = index::build(root)?;
+        println!("{}: {} files indexed, {} read, {} removed", path, stats.files, stats.read, stats.removed);
+        unreadable += stats.unreadable;
+    }
+    if unreadable > 0 {
+        return Err(Error::Unreadable(unreadable));
+    }
+    Ok(true)
+}
+
+// --replace: print a diff per file, or rewrite the files with --in-place
+fn replace_files(config: &Config, matcher: &PatternSet, template: &str, files: &[(PathBuf, bool)]) -> error::Result<bool> {
+    if config.normalize {
+        return Err(Error::Usage("--replace cannot be combined with --normalize"));
+    }
+    if files.iter().any(|(file, _)| file == Path::new(STDIN_PATH)) {
+        return Err(Error::Usage("--replace needs files, not standard input"));
+    }
+    // one regex for all patterns; with several, the groups of a regex pattern
+    // are numbered after those of the patterns before it
+    let sources: Vec<String> = matcher
+        .patterns()
+        .iter()
+        .map(|p| if config.regex { p.clone() } else { regex::escape(p) })
+        .collect();
+    let source = match sources.as_slice() {
+        [single] => single.clone(),
+        _ => sources.iter().map(|p| format!("(?:{})", p)).collect::<Vec<_>>().join("|"),
+    };
+    let re = &Config::build_regex(&source, config.case_sensitive)?;
+
+    let results: Vec<_> = files
+        .par_iter()
+        .map(|(file, _)| replace::replace_file(re, template, file, config.encoding, config.in_place, config.backup))
+        .collect();
+
+    let mut changed = false;
+    let mut failed = 0;
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for ((file, _), result) in files.iter().zip(results) {
+        match result {
+            Ok((diff, file_changed)) => {
+                changed |= file_changed;
+                stdout.write_all(diff.as_bytes())?;
+            }
+            // the other files still get their diffs
+            Err(err) => {
+                eprintln!("{}: {}", file.display(), err);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(Error::Unreadable(failed));
+    }
+    Ok(changed)
+}
+
+// --rank: the matching lines of every file, closest first; equally close
+// lines keep their input order
+fn rank_files(config: &Config, matcher: &PatternSet, files: &[(PathBuf, bool)], with_path: bool) -> error::Result<bool> {
+    let searcher = Searcher::new(matcher);
+    // (distance, line index, line) of every matching line of a file
+    let results: Vec<io::Result<Vec<(usize, usize, String)>>> = files
+        .par_iter()
+        .map(|(file, _)| {
+            let mut lines: Vec<(usize, usize, String)> = Vec::new();
+            searcher.search_reader(open_input(config, file)?, |m: &Match| {
+                if let Some(last) = lines.last_mut().filter(|last| last.1 == m.line - 1) {
+                    last.0 = last.0.min(m.distance);
+                } else if config.max_count.is_some_and(|max| lines.len() >= max) {
+                    return Ok(false);
+                } else {
+                    lines.push((m.distance, m.line - 1, m.line_text.to_string()));
+                }
+                Ok(true)
+            })?;
+            Ok(lines)
+        })
+        .collect();
+
+    let mut ranked = Vec::new();
+    let mut failed = 0;
+    for ((file, _), result) in files.iter().zip(results) {
+        match result {
+            Ok(lines) => ranked.extend(lines.into_iter().map(|(distance, index, line)| (distance, file, index, line))),
+            // the lines of the other files are still ranked
+            Err(err) => {
+                eprintln!("{}: {}", file.display(), err);
+                failed += 1;
+            }
         }
     }
-    // search
-    // print
-    Ok(())
+    ranked.sort_by_key(|(distance, ..)| *distance);
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for (distance, file, index, line) in ranked.iter() {
+        let path = if with_path { Some(display_name(file)) } else { None };
+        let matches = config.highlights(matcher, line);
+        stdout.write_all(config.format_line(path, *index, line, true, Some(*distance), &matches).as_bytes())?;
+    }
+    if failed > 0 {
+        return Err(Error::Unreadable(failed));
+    }
+    Ok(!ranked.is_empty())
+}
+
+// how a file is named in the output
+fn display_name(file: &Path) -> &Path {
+    if file == Path::new(STDIN_PATH) { Path::new(STDIN_LABEL) } else { file }
+}
+
+// open a file, or stdin for `-`, decoded to UTF-8
+fn open_input(config: &Config, file: &Path) -> io::Result<Box<dyn BufRead>> {
+    let reader: Box<dyn Read> = if file == Path::new(STDIN_PATH) {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(File::open(file)?)
+    };
+    encoding::decode_reader(reader, config.encoding)
+}
+
+// search one file, or stdin for `-`, writing its output to `out`; returns
+// whether the file counts as a success for the exit code
+fn search_file<W: Write>(config: &Config, matcher: &PatternSet, file: &Path, with_path: bool, out: &mut W) -> io::Result<bool> {
+    let name = display_name(file);
+    let path = if with_path || config.json { Some(name) } else { None };
+    let count = search_input(config, matcher, open_input(config, file)?, path, out)?;
+
+    if config.files_with_matches && count > 0 || config.files_without_match && count == 0 {
+        writeln!(out, "{}", config.paint(color::PATH, &name.display().to_string()))?;
+    } else if config.count {
+        match path {
+            Some(path) => writeln!(out, "{}{}{}", config.paint(color::PATH, &path.display().to_string()), config.paint(color::SEPARATOR, ":"), count)?,
+            None => writeln!(out, "{}", count)?,
+        }
+    }
+    // -L succeeds when it lists a file
+    Ok(if config.files_without_match { count == 0 } else { count > 0 })
+}
+
+// search decoded input the way the flags ask, returns how many lines, blocks
+// or matches were selected
+fn search_input<R: BufRead, W: Write>(config: &Config, matcher: &PatternSet, reader: R, path: Option<&Path>, out: &mut W) -> io::Result<usize> {
+    if config.multiline {
+        multiline::search_reader(config, matcher, reader, path, out)
+    } else if config.scope != scope::Scope::Line {
+        scope::search_reader(config, matcher, reader, path, out)
+    } else {
+        search::search_reader(config, matcher, reader, path, out)
+    }
+}
+
+// what the command line asks for
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Action {
+    #[default]
+    Search,
+    // `index build`: write the index of each path
+    IndexBuild,
+    // `index query`: search, letting the index of each directory pick the files
+    IndexQuery,
 }
 
 // Config struct
-#[derive(Debug,PartialEq)]
+#[derive(Debug, Default, PartialEq)]
 pub struct Config {
-    target: String,
-    path: String,
+    action: Action,
+    patterns: Vec<String>,
+    pattern_files: Vec<String>,
+    paths: Vec<String>,
     show_line_number: bool,
     case_sensitive: bool,
+    regex: bool,
+    before_context: usize,
+    after_context: usize,
+    follow: bool,
+    encoding: Option<&'static Encoding>,
+    normalize: bool,
+    json: bool,
+    invert: bool,
+    count: bool,
+    files_with_matches: bool,
+    files_without_match: bool,
+    only_matching: bool,
+    max_count: Option<usize>,
+    replace: Option<String>,
+    in_place: bool,
+    backup: bool,
+    fuzzy: Option<usize>,
+    rank: bool,
+    section: Option<String>,
+    in_section: Vec<String>,
+    urls: bool,
+    params: Vec<urls::ParamFilter>,
+    // --newer-than / --older-than
+    modified: dates::Window,
+    // --since / --until
+    logged: dates::Window,
+    interactive: bool,
+    // `auto` until `run` settles it
+    color: color::When,
+    multiline: bool,
+    query: Option<String>,
+    scope: scope::Scope,
+    // --glob / --type
+    globs: Vec<String>,
+    types: Vec<String>,
+    binary: bool,
 }
 
 // imple config new method
 
 impl Config {
-   pub fn new(args: &[String]) -> Result<Config, &str> {
-        if args.len() < 3 {
-            return Err("not enough arguments");
+   pub fn new(args: &[String]) -> Result<Config, Error> {
+        if !cli::is_index_command(args) {
+            return Config::from_args(cli::Args::try_parse_from(args)?);
+        }
+        match cli::IndexArgs::try_parse_from(&args[1..])?.command {
+            cli::IndexCommand::Build { dir } => Ok(Config { action: Action::IndexBuild, paths: vec![dir], ..Config::default() }),
+            cli::IndexCommand::Query { args } => {
+                let args: Vec<String> = std::iter::once(String::from("rust_demo index query")).chain(args).collect();
+                let mut config = Config::from_args(cli::Args::try_parse_from(&args)?)?;
+                // an index belongs to a directory, so there is no stdin to fall back to
+                if config.paths == [STDIN_PATH] {
+                    config.paths = vec![String::from(".")];
+                }
+                Ok(Config { action: Action::IndexQuery, ..config })
+            }
+        }
+    }
+
+    // the search the command line asks for, plain or through `index query`
+    fn from_args(args: cli::Args) -> Result<Config, Error> {
+        // patterns come from -e / -f, or else the first positional is the pattern;
+        // the other positionals are files or directories
+        let mut positional = args.positional.into_iter();
+        let mut patterns = args.patterns;
+        // --interactive asks for the query itself, --query is one
+        if patterns.is_empty() && args.pattern_files.is_empty() && !args.interactive && args.query.is_none() {
+            patterns.push(positional.next().ok_or(Error::MissingArgs)?);
+        }
+        let mut paths: Vec<String> = positional.collect();
+        if paths.is_empty() {
+            paths.push(STDIN_PATH.to_string());
+        }
+
+        let scope = args.scope.unwrap_or_default();
+        if scope == scope::Scope::Section && args.section.is_none() {
+            return Err(Error::Usage("--scope section needs --section to tell the headings"));
         }
 
-        let target = args[1].clone();
-        let path = args[2].clone();
-        let show_line_number = args.contains(&String::from("-n"));
-        let case_sensitive = args.contains(&String::from("-s"));
-        
-        Ok(Config { target, path, show_line_number, case_sensitive })
+        let encoding = match args.encoding {
+            Some(label) => Some(encoding::for_label(&label).ok_or(Error::UnknownEncoding(label))?),
+            None => None,
+        };
+
+        Ok(Config {
+            action: Action::Search,
+            patterns,
+            pattern_files: args.pattern_files,
+            paths,
+            show_line_number: args.line_number,
+            case_sensitive: args.case_sensitive,
+            regex: args.regex,
+            // -A / -B win over -C whatever their order
+            before_context: args.before_context.or(args.context).unwrap_or(0),
+            after_context: args.after_context.or(args.context).unwrap_or(0),
+            follow: args.follow,
+            encoding,
+            normalize: args.normalize,
+            json: args.json,
+            invert: args.invert,
+            count: args.count,
+            files_with_matches: args.files_with_matches,
+            files_without_match: args.files_without_match,
+            only_matching: args.only_matching,
+            max_count: args.max_count,
+            replace: args.replace,
+            in_place: args.in_place,
+            backup: args.backup,
+            fuzzy: args.fuzzy,
+            rank: args.rank,
+            section: args.section,
+            in_section: args.in_section,
+            urls: args.urls,
+            params: args.params.iter().map(|spec| urls::ParamFilter::parse(spec)).collect(),
+            modified: dates::Window { since: args.newer_than, until: args.older_than },
+            logged: dates::Window { since: args.since, until: args.until },
+            interactive: args.interactive,
+            color: args.color,
+            multiline: args.multiline,
+            query: args.query,
+            scope,
+            globs: args.globs,
+            types: args.types,
+            binary: args.binary,
+        })
     }
   pub fn search_case_insensitive<'a>(query: &str, contents: &'a str) -> Vec<(usize, &'a str)> {
-
+    // fold the query once instead of lowercasing every line
+    let finder = CaseInsensitive::new(query);
     contents.lines()
     .enumerate()
-    .filter(|(_, line)| line.to_lowercase().contains(&query.to_lowercase()))
+    .filter(|(_, line)| finder.is_match(line))
     .collect()
-  }    
+  }
   pub fn search_case_sensitive<'a>(query: &str, contents: &'a str) -> Vec<(usize, &'a str)> {
     contents.lines()
     .enumerate()
-    .filter(|(_, line)| line.contains(&query))
+    .filter(|(_, line)| line.contains(query))
     .collect()
 }
+
+  // -c / -l / -L only report per file, nothing is printed for each match
+  fn quiet(&self) -> bool {
+    self.count || self.files_with_matches || self.files_without_match
+  }
+
+  // how many matches to select per file, one is enough for the file lists
+  fn limit(&self) -> Option<usize> {
+    if self.files_with_matches || self.files_without_match { Some(1) } else { self.max_count }
+  }
+
+  // grep style prefixes: `:` after the path / line number of a match, `-` for context,
+  // then `~N ` with the edit distance of a --fuzzy match; with colour, `matches`
+  // are the byte ranges of `text` to highlight
+  fn format_line(&self, path: Option<&Path>, index: usize, text: &str, is_match: bool, distance: Option<usize>, matches: &[Range<usize>]) -> String {
+    let sep = if is_match { ":" } else { "-" };
+    let mut line = String::new();
+    if let Some(path) = path {
+        line.push_str(&self.paint(color::PATH, &path.display().to_string()));
+        line.push_str(&self.paint(color::SEPARATOR, sep));
+    }
+    if self.show_line_number {
+        line.push_str(&self.paint(color::LINE_NUMBER, &(index + 1).to_string()));
+        line.push_str(&self.paint(color::SEPARATOR, sep));
+        line.push(' ');
+    }
+    if let Some(distance) = distance {
+        line.push_str(&format!("~{} ", distance));
+    }
+    if self.color == color::When::Always {
+        line.push_str(&color::highlight(text, matches));
+    } else {
+        line.push_str(text);
+    }
+    line.push('\n');
+    line
+  }
+
+  fn paint(&self, code: &str, text: &str) -> String {
+    if self.color == color::When::Always { color::paint(code, text) } else { text.to_string() }
+  }
+
+  // where to highlight a selected line; nothing without colour, and an
+  // inverted match has nothing in it to show
+  fn highlights(&self, matcher: &PatternSet, text: &str) -> Vec<Range<usize>> {
+    if self.color != color::When::Always || self.invert {
+        return Vec::new();
+    }
+    matcher.find_spans(text).into_iter().map(|span| span.range).collect()
+  }
+
+  // compile the target as a regex, case-insensitivity goes into the pattern itself
+  pub fn build_regex(pattern: &str, case_sensitive: bool) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern)
+    .case_insensitive(!case_sensitive)
+    .build()
+  }
+
+  pub fn search_regex<'a>(re: &Regex, contents: &'a str) -> Vec<(usize, &'a str)> {
+    contents.lines()
+    .enumerate()
+    .filter(|(_, line)| re.is_match(line))
+    .collect()
+  }
 }
 
 // tdd
@@ -64,6 +557,20 @@ impl Config {
 mod tests {
     use super::*;
 
+    // the argv `Config::new` takes
+    pub(crate) fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+
+    // search `input` with the flags in `args`, through the reader they pick;
+    // returns how many were selected and what was printed
+    pub(crate) fn run_search(args: &[&str], input: &str) -> (usize, String) {
+        let config = Config::new(&argv(args)).unwrap();
+        let mut out = Vec::new();
+        let selected = search_input(&config, &PatternSet::new(&config).unwrap(), input.as_bytes(), None, &mut out).unwrap();
+        (selected, String::from_utf8(out).unwrap())
+    }
+
     #[test]
     fn test_new_config() {
         let args = vec![
@@ -72,8 +579,142 @@ mod tests {
             String::from("path"),
         ];
         let config = Config::new(&args).unwrap();
-        assert_eq!(config.target, "target");
-        assert_eq!(config.path, "path");
+        assert_eq!(config.patterns, vec!["target"]);
+        assert_eq!(config.paths, vec!["path"]);
+    }
+
+    #[test]
+    fn test_new_config_stdin() {
+        let args = argv(&["program_name", "target", "-n"]);
+        assert_eq!(Config::new(&args).unwrap().paths, vec!["-"]);
+        let args = argv(&["program_name", "target", "-", "a.txt"]);
+        assert_eq!(Config::new(&args).unwrap().paths, vec!["-", "a.txt"]);
+    }
+
+    #[test]
+    fn test_new_config_many_paths() {
+        let args = argv(&["program_name", "target", "a.txt", "-n", "src", "res/wap"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!(config.paths, vec!["a.txt", "src", "res/wap"]);
+        assert!(config.show_line_number);
+    }
+
+    #[test]
+    fn test_new_config_encoding() {
+        let args = argv(&["program_name", "广东", "a.txt", "--encoding", "GBK"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!(config.encoding, Some(encoding_rs::GBK));
+        assert_eq!(config.paths, vec!["a.txt"]);
+
+        let args = argv(&["program_name", "广东", "a.txt", "--encoding", "klingon"]);
+        assert!(matches!(Config::new(&args), Err(Error::UnknownEncoding(label)) if label == "klingon"));
+    }
+
+    #[test]
+    fn test_new_config_patterns() {
+        let args = argv(&["program_name", "-e", "苹果", "a.txt", "-f", "codes.txt", "-e", "-n", "b.txt"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!(config.patterns, vec!["苹果", "-n"]);
+        assert_eq!(config.pattern_files, vec!["codes.txt"]);
+        assert_eq!(config.paths, vec!["a.txt", "b.txt"]);
+    }
+
+    #[test]
+    fn test_new_config_context() {
+        let args = argv(&["program_name", "target", "a.txt", "-C", "2", "-A", "5"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!(config.paths, vec!["a.txt"]);
+        assert_eq!((config.before_context, config.after_context), (2, 5));
+
+        let args = argv(&["program_name", "target", "a.txt", "-B"]);
+        assert!(Config::new(&args).is_err());
+    }
+
+    #[test]
+    fn test_new_config_combined_flags() {
+        let args = argv(&["program_name", "-nsA1", "苹果", "a.txt"]);
+        let config = Config::new(&args).unwrap();
+        assert!(config.show_line_number && config.case_sensitive);
+        assert_eq!((config.before_context, config.after_context), (0, 1));
+        assert_eq!(config.paths, vec!["a.txt"]);
+    }
+
+    #[test]
+    fn test_new_config_double_dash() {
+        // after `--` a file may be called `-n`, and a pattern may start with `-`
+        let args = argv(&["program_name", "-n", "--", "-s", "-n"]);
+        let config = Config::new(&args).unwrap();
+        assert!(config.show_line_number && !config.case_sensitive);
+        assert_eq!(config.patterns, vec!["-s"]);
+        assert_eq!(config.paths, vec!["-n"]);
+    }
+
+    #[test]
+    fn test_new_config_bad_flags() {
+        let args = argv(&["program_name", "--help"]);
+        assert!(matches!(Config::new(&args), Err(Error::Args(err)) if err.kind() == clap::error::ErrorKind::DisplayHelp));
+        let args = argv(&["program_name", "target", "--frobnicate"]);
+        assert!(matches!(Config::new(&args), Err(Error::Args(_))));
+        let args = argv(&["program_name", "target", "a.txt", "--in-place"]);
+        assert!(Config::new(&args).is_err());
+        // a diff has no inverted, limited, counted, json or section form
+        for flag in [&["-v"][..], &["-m", "1"], &["-c"], &["-l"], &["-o"], &["--json"], &["--section", "^#"], &["--since", "2025-09-09"]] {
+            let args = argv(&[&["program_name", "target", "a.txt", "--replace", "x"][..], flag].concat());
+            assert!(Config::new(&args).is_err(), "{:?}", flag);
+        }
+        // appended lines are printed as plain lines, so no other output formats
+        let args = argv(&["program_name", "target", "a.txt", "--follow", "--json"]);
+        assert!(Config::new(&args).is_err());
+        // nor a diff, context or a line limit
+        for flag in [&["--replace", "x"][..], &["-C", "2"], &["-A", "1"], &["-m", "3"]] {
+            let args = argv(&[&["program_name", "target", "a.txt", "--follow"][..], flag].concat());
+            assert!(Config::new(&args).is_err(), "{:?}", flag);
+        }
+    }
+
+    #[test]
+    fn test_new_config_fuzzy() {
+        let args = argv(&["program_name", "--fuzzy", "2", "--rank", "苹果", "a.txt"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!((config.fuzzy, config.rank), (Some(2), true));
+        // ranking needs distances, and a fuzzy pattern is not a regex
+        let args = argv(&["program_name", "--rank", "苹果", "a.txt"]);
+        assert!(Config::new(&args).is_err());
+        let args = argv(&["program_name", "--fuzzy", "1", "-E", "苹果", "a.txt"]);
+        assert!(Config::new(&args).is_err());
+    }
+
+    #[test]
+    fn test_new_config_index() {
+        let args = argv(&["program_name", "index", "build", "src"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!((config.action, config.paths), (Action::IndexBuild, vec![String::from("src")]));
+        // a query takes every search flag and searches the current directory by default
+        let args = argv(&["program_name", "index", "query", "-n", "苹果"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!(config.action, Action::IndexQuery);
+        assert!(config.show_line_number);
+        assert_eq!((config.patterns, config.paths), (vec![String::from("苹果")], vec![String::from(".")]));
+        // searching for `index` itself
+        let args = argv(&["program_name", "-e", "index", "a.txt"]);
+        assert_eq!(Config::new(&args).unwrap().action, Action::Search);
+        // `index` or `help` followed by anything else is a pattern
+        let config = Config::new(&argv(&["program_name", "index", "a.txt"])).unwrap();
+        assert_eq!((config.action, config.patterns), (Action::Search, vec![String::from("index")]));
+        assert_eq!(Config::new(&argv(&["program_name", "help", "a.txt"])).unwrap().patterns, vec!["help"]);
+        // and a query may search for `index build`
+        let config = Config::new(&argv(&["program_name", "index", "query", "index", "build"])).unwrap();
+        assert_eq!((config.action, config.patterns, config.paths), (Action::IndexQuery, vec![String::from("index")], vec![String::from("build")]));
+    }
+
+    #[test]
+    fn test_new_config_file_filters() {
+        let args = argv(&["program_name", "-g", "*.css", "--glob", "!*.min.js", "--type", "css,js", "-t", "html", "--binary", "a", "web"]);
+        let config = Config::new(&args).unwrap();
+        assert_eq!(config.globs, vec!["*.css", "!*.min.js"]);
+        assert_eq!(config.types, vec!["css", "js", "html"]);
+        assert!(config.binary);
+        assert_eq!((config.patterns, config.paths), (vec![String::from("a")], vec![String::from("web")]));
     }
 
     #[test]
@@ -82,9 +723,9 @@ mod tests {
             String::from("target"),
         ];
         let config = Config::new(&args);
-        assert_eq!(config, Err("not enough arguments"));
+        assert!(matches!(config, Err(Error::MissingArgs)));
     }
-    #[test] 
+    #[test]
     // case_sensitive
     fn test_search_case_sensitive() {
         let query = "duct";
@@ -94,10 +735,10 @@ safe, fast, productive.
 Pick three.
 Duct tape.";
         assert_eq!(
-            vec![(2, "safe, fast, productive.")],
+            vec![(1, "safe, fast, productive.")],
             Config::search_case_sensitive(query, contents)
         );
-        
+
         }
     #[test]
     // case_insensitive
@@ -114,5 +755,29 @@ Trust me.";
         );
     }
 
+    #[test]
+    // regex
+    fn test_search_regex() {
+        let contents = "\
+productCode%3D2025090916365395401379458&net_token=1
+activityId%3D200024335&net_token=2
+PRODUCTCODE%3D42";
+        let re = Config::build_regex(r"productCode%3D\d+", true).unwrap();
+        assert_eq!(
+            vec![(0, "productCode%3D2025090916365395401379458&net_token=1")],
+            Config::search_regex(&re, contents)
+        );
+        let re = Config::build_regex(r"productCode%3D\d+", false).unwrap();
+        assert_eq!(2, Config::search_regex(&re, contents).len());
+    }
+
+    #[test]
+    fn test_new_config_regex_flag() {
+        let args = argv(&["program_name", r"\d+", "path", "--regex"]);
+        let config = Config::new(&args).unwrap();
+        assert!(config.regex);
+        assert!(Config::build_regex("(", true).is_err());
+    }
 
-}
\ No newline at end of file
+
+}
@@ -4,14 +4,22 @@ fn main() {
     // config
     let args = std::env::args().collect::<Vec<String>>();
 
-    let config = rust_demo::Config::new(&args).unwrap_or_else(|err| {
-        eprintln!("Problem parsing arguments: {}", err);
-        std::process::exit(1);
+    let config = rust_demo::Config::new(&args).unwrap_or_else(|err| match err {
+        // clap prints usage and --help / --version itself, with the right exit code
+        rust_demo::Error::Args(err) => err.exit(),
+        err => {
+            eprintln!("Problem parsing arguments: {}", err);
+            std::process::exit(2);
+        }
     });
 
-    rust_demo::run(config).unwrap_or_else(|err| {
-        eprintln!("Application error: {}", err);
-        std::process::exit(1);
-    });
+    // grep exit codes: 0 something was selected, 1 nothing was, 2 error
+    match rust_demo::run(config) {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(err) => {
+            eprintln!("Application error: {}", err);
+            std::process::exit(2);
+        }
+    }
 }
-
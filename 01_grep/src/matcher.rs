@@ -0,0 +1,538 @@
+use std::fs::File;
+use std::io::{self, BufRead};
+use std::ops::Range;
+use std::path::Path;
+
+use aho_corasick::{AhoCorasick, MatchKind};
+use memchr::memmem;
+use regex::{Regex, RegexSet};
+
+use crate::casefold::CaseInsensitive;
+use crate::error::{self, Error};
+use crate::fuzzy::Fuzzy;
+use crate::query::{self, Expr};
+use crate::{encoding, normalize, Config};
+
+// where a match sits in its line, as byte ranges of the original line text
+#[derive(Debug, PartialEq)]
+pub struct Span {
+    pub range: Range<usize>,
+    // index of the pattern that matched: -e patterns first, then -f file lines
+    pub pattern: usize,
+    // capture groups 1.. of a regex, `None` for a group that did not take part
+    pub submatches: Vec<Option<Submatch>>,
+    // --fuzzy: edits between the pattern and the matched text
+    pub distance: Option<usize>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Submatch {
+    pub name: Option<String>,
+    pub range: Range<usize>,
+}
+
+// a match found by a `Matcher`, `range` is in bytes of the line
+#[derive(Debug, Clone, PartialEq)]
+pub struct Hit {
+    pub range: Range<usize>,
+    // edits between the query and the matched text, 0 unless matching is fuzzy
+    pub distance: usize,
+}
+
+// finds a query in a line; every kind of search implements it, so `Searcher`
+// can run any of them and other tools can plug in their own
+pub trait Matcher {
+    // the leftmost match starting at or after byte `start` of `line`
+    fn find_at(&self, line: &str, start: usize) -> Option<Hit>;
+
+    fn is_match(&self, line: &str) -> bool {
+        self.find_at(line, 0).is_some()
+    }
+}
+
+impl<M: Matcher + ?Sized> Matcher for &M {
+    fn find_at(&self, line: &str, start: usize) -> Option<Hit> {
+        (**self).find_at(line, start)
+    }
+}
+
+impl<M: Matcher + ?Sized> Matcher for Box<M> {
+    fn find_at(&self, line: &str, start: usize) -> Option<Hit> {
+        (**self).find_at(line, start)
+    }
+}
+
+// an exact, case-sensitive substring
+pub struct Literal {
+    finder: memmem::Finder<'static>,
+}
+
+impl Literal {
+    pub fn new(query: &str) -> Literal {
+        Literal { finder: memmem::Finder::new(query.as_bytes()).into_owned() }
+    }
+}
+
+impl Matcher for Literal {
+    fn find_at(&self, line: &str, start: usize) -> Option<Hit> {
+        let found = start + self.finder.find(&line.as_bytes()[start..])?;
+        Some(Hit { range: found..found + self.finder.needle().len(), distance: 0 })
+    }
+}
+
+impl Matcher for CaseInsensitive {
+    fn find_at(&self, line: &str, start: usize) -> Option<Hit> {
+        let found = self.find(&line.as_bytes()[start..])?;
+        Some(Hit { range: start + found.start..start + found.end, distance: 0 })
+    }
+}
+
+impl Matcher for Regex {
+    fn find_at(&self, line: &str, start: usize) -> Option<Hit> {
+        Regex::find_at(self, line, start).map(|m| Hit { range: m.range(), distance: 0 })
+    }
+}
+
+// every pattern of a search compiled once, so each line is scanned a single
+// time no matter how many patterns there are
+pub struct PatternSet {
+    patterns: Vec<String>,
+    kind: Kind,
+    // --normalize: lines are folded first, `Some(case_sensitive)` for the fold
+    normalize: Option<bool>,
+    // --section: what a heading line looks like
+    section: Option<Regex>,
+    // --query: the patterns are its terms, and it decides what matches
+    query: Option<Boolean>,
+}
+
+enum Kind {
+    // the set says if any regex matches, the regexes are only run for spans
+    Regex { set: RegexSet, regexes: Vec<Regex> },
+    // all literals in one Aho-Corasick automaton
+    Literal(AhoCorasick),
+    // literals with non-ASCII letters matched ignoring case
+    CaseFolded(Vec<CaseInsensitive>),
+    // --fuzzy: literals matched within an edit budget
+    Fuzzy(Vec<Fuzzy>),
+}
+
+struct Boolean {
+    expr: Expr,
+    // one matcher per term, `kind` still finds them all at once for spans
+    terms: Vec<Kind>,
+    // the terms worth highlighting, see `Expr::positive`
+    positive: Vec<bool>,
+}
+
+impl PatternSet {
+    // compile the patterns of `config`, reading the -f pattern files, or the
+    // terms of its --query
+    pub fn new(config: &Config) -> error::Result<PatternSet> {
+        let mut patterns = config.patterns.clone();
+        for file in config.pattern_files.iter() {
+            patterns.extend(read_patterns(file).map_err(|err| Error::io(Path::new(file), err))?);
+        }
+        let query = match &config.query {
+            Some(text) => Some(query::parse(text).map_err(Error::Query)?),
+            None => None,
+        };
+        if let Some(query) = &query {
+            patterns = query.terms.clone();
+        }
+        let normalize = if config.normalize {
+            // a regex keeps its own case handling, only literals get case folded
+            Some(config.regex || config.case_sensitive)
+        } else {
+            None
+        };
+
+        let kind = Kind::new(config, &patterns, normalize)?;
+        let query = match query {
+            Some(query) => {
+                let terms = patterns
+                    .iter()
+                    .map(|p| Kind::new(config, std::slice::from_ref(p), normalize))
+                    .collect::<error::Result<Vec<_>>>()?;
+                let mut positive = vec![false; patterns.len()];
+                query.expr.positive(false, &mut positive);
+                Some(Boolean { expr: query.expr, terms, positive })
+            }
+            None => None,
+        };
+        let section = match &config.section {
+            Some(section) => Some(Config::build_regex(section, config.case_sensitive)?),
+            None => None,
+        };
+        Ok(PatternSet { patterns, kind, normalize, section, query })
+    }
+
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+
+    pub fn is_heading(&self, line: &str) -> bool {
+        self.section.as_ref().is_some_and(|section| section.is_match(line))
+    }
+
+    pub fn is_match(&self, line: &str) -> bool {
+        let folded;
+        let line = match self.normalize {
+            Some(case_sensitive) => {
+                folded = normalize::fold(line, case_sensitive).text;
+                folded.as_str()
+            }
+            None => line,
+        };
+        match &self.query {
+            Some(query) => query.expr.eval(&|term| query.terms[term].is_match(line)),
+            None => self.kind.is_match(line),
+        }
+    }
+
+    // --scope: whether a block of lines matches; a query term is in the block
+    // when any of its lines has it, without a query any matching line will do
+    pub fn is_block_match(&self, lines: &[&str]) -> bool {
+        let folded: Vec<String> = match self.normalize {
+            Some(case_sensitive) => lines.iter().map(|line| normalize::fold(line, case_sensitive).text).collect(),
+            None => Vec::new(),
+        };
+        let lines: Vec<&str> = if self.normalize.is_some() { folded.iter().map(String::as_str).collect() } else { lines.to_vec() };
+        match &self.query {
+            Some(query) => query.expr.eval(&|term| lines.iter().any(|line| query.terms[term].is_match(line))),
+            None => lines.iter().any(|line| self.kind.is_match(line)),
+        }
+    }
+
+    // whether `find_bytes` can look for matches in a whole buffer of lines
+    pub fn can_find_bytes(&self) -> bool {
+        // a query with NOT in it may match lines without any term
+        self.normalize.is_none()
+            && self.query.is_none()
+            && match &self.kind {
+                Kind::Literal(_) => true,
+                Kind::CaseFolded(finders) => finders.len() == 1,
+                Kind::Regex { .. } | Kind::Fuzzy(_) => false,
+            }
+    }
+
+    // start of the first match in a raw buffer, only when `can_find_bytes`;
+    // lets the searcher skip straight past lines that cannot match
+    pub fn find_bytes(&self, haystack: &[u8]) -> Option<usize> {
+        match &self.kind {
+            Kind::Literal(automaton) => automaton.find(haystack).map(|m| m.start()),
+            Kind::CaseFolded(finders) => finders[0].find(haystack).map(|m| m.start),
+            Kind::Regex { .. } | Kind::Fuzzy(_) => unreachable!("regexes and fuzzy patterns are searched line by line"),
+        }
+    }
+
+    // every non-overlapping match in `line`, leftmost first; with --query
+    // only those of the terms it does not negate
+    pub fn find_spans(&self, line: &str) -> Vec<Span> {
+        let mut spans = match self.normalize {
+            Some(case_sensitive) => {
+                let folded = normalize::fold(line, case_sensitive);
+                let mut spans: Vec<Span> = self
+                    .kind
+                    .spans(&folded.text)
+                    .into_iter()
+                    .map(|span| Span {
+                        range: folded.original_range(span.range),
+                        submatches: span
+                            .submatches
+                            .into_iter()
+                            .map(|sub| sub.map(|sub| Submatch { range: folded.original_range(sub.range), ..sub }))
+                            .collect(),
+                        ..span
+                    })
+                    .collect();
+                // matches inside one char that folds to several, like `f` in `ﬃ`,
+                // all map back to that whole char
+                drop_overlaps(&mut spans);
+                spans
+            }
+            None => self.kind.spans(line),
+        };
+        if let Some(query) = &self.query {
+            spans.retain(|span| query.positive[span.pattern]);
+        }
+        spans
+    }
+}
+
+// the patterns of a `Config` as one matcher, whichever pattern comes first wins
+impl Matcher for PatternSet {
+    fn find_at(&self, line: &str, start: usize) -> Option<Hit> {
+        let span = self.find_spans(&line[start..]).into_iter().next()?;
+        Some(Hit { range: start + span.range.start..start + span.range.end, distance: span.distance.unwrap_or(0) })
+    }
+
+    fn is_match(&self, line: &str) -> bool {
+        PatternSet::is_match(self, line)
+    }
+}
+
+impl Kind {
+    fn new(config: &Config, patterns: &[String], normalize: Option<bool>) -> error::Result<Kind> {
+        if config.regex || config.multiline {
+            let sources: Vec<String> = if config.multiline {
+                patterns.iter().map(|p| multiline_source(p, config.regex)).collect()
+            } else {
+                patterns.to_vec()
+            };
+            let regexes = sources
+                .iter()
+                .map(|p| Config::build_regex(p, config.case_sensitive))
+                .collect::<Result<Vec<_>, _>>()?;
+            let set = regex::RegexSetBuilder::new(&sources).case_insensitive(!config.case_sensitive).build()?;
+            return Ok(Kind::Regex { set, regexes });
+        }
+        let literals: Vec<String> = match normalize {
+            Some(case_sensitive) => patterns.iter().map(|p| normalize::fold(p, case_sensitive).text).collect(),
+            None => patterns.to_vec(),
+        };
+        let kind = if let Some(max_edits) = config.fuzzy {
+            let case_sensitive = normalize.is_some() || config.case_sensitive;
+            Kind::Fuzzy(literals.iter().map(|p| Fuzzy::new(p, max_edits, case_sensitive)).collect())
+        } else if normalize.is_some() || config.case_sensitive {
+            Kind::Literal(AhoCorasick::builder().match_kind(MatchKind::LeftmostFirst).build(&literals)?)
+        } else if literals.iter().all(|p| p.is_ascii()) {
+            Kind::Literal(
+                AhoCorasick::builder()
+                    .match_kind(MatchKind::LeftmostFirst)
+                    .ascii_case_insensitive(true)
+                    .build(&literals)?,
+            )
+        } else {
+            Kind::CaseFolded(literals.iter().map(|p| CaseInsensitive::new(p)).collect())
+        };
+        Ok(kind)
+    }
+
+    fn is_match(&self, line: &str) -> bool {
+        match self {
+            Kind::Regex { set, .. } => set.is_match(line),
+            Kind::Literal(automaton) => automaton.is_match(line),
+            Kind::CaseFolded(finders) => finders.iter().any(|finder| finder.is_match(line)),
+            Kind::Fuzzy(finders) => finders.iter().any(|finder| finder.is_match(line)),
+        }
+    }
+
+    fn spans(&self, line: &str) -> Vec<Span> {
+        let mut spans: Vec<Span> = match self {
+            Kind::Regex { set, regexes } => set
+                .matches(line)
+                .into_iter()
+                .flat_map(|pattern| regex_spans(&regexes[pattern], pattern, line))
+                .collect(),
+            Kind::Literal(automaton) => automaton
+                .find_iter(line)
+                .map(|m| Span { range: m.range(), pattern: m.pattern().as_usize(), submatches: Vec::new(), distance: None })
+                .collect(),
+            Kind::CaseFolded(finders) => finders
+                .iter()
+                .enumerate()
+                .flat_map(|(pattern, finder)| {
+                    finder.find_iter(line).map(move |range| Span { range, pattern, submatches: Vec::new(), distance: None })
+                })
+                .collect(),
+            Kind::Fuzzy(finders) => finders
+                .iter()
+                .enumerate()
+                .flat_map(|(pattern, finder)| fuzzy_spans(finder, pattern, line))
+                .collect(),
+        };
+        // an empty pattern matches everywhere but there is nothing to point at
+        spans.retain(|span| !span.range.is_empty());
+        // several patterns may hit the same text, keep the leftmost, then the first pattern
+        spans.sort_by_key(|span| (span.range.start, span.pattern));
+        drop_overlaps(&mut spans);
+        spans
+    }
+}
+
+// keep the first of spans sorted by start that overlap
+fn drop_overlaps(spans: &mut Vec<Span>) {
+    let mut end = 0;
+    spans.retain(|span| {
+        let keep = span.range.start >= end;
+        if keep {
+            end = span.range.end;
+        }
+        keep
+    });
+}
+
+// -U: `^` and `$` match at every line, and the whitespace of a literal matches
+// any run of whitespace, so a phrase wrapped onto the next line is found
+fn multiline_source(pattern: &str, regex: bool) -> String {
+    if regex {
+        return format!("(?m){}", pattern);
+    }
+    let mut source = String::new();
+    let mut in_space = false;
+    for c in pattern.chars() {
+        if !c.is_whitespace() {
+            source.push_str(&regex::escape(c.encode_utf8(&mut [0; 4])));
+        } else if !in_space {
+            source.push_str(r"\s+");
+        }
+        in_space = c.is_whitespace();
+    }
+    source
+}
+
+fn regex_spans(re: &Regex, pattern: usize, line: &str) -> Vec<Span> {
+    re.captures_iter(line)
+        .map(|caps| Span {
+            range: caps.get(0).unwrap().range(),
+            pattern,
+            submatches: re
+                .capture_names()
+                .zip(caps.iter())
+                .skip(1)
+                .map(|(name, group)| group.map(|group| Submatch { name: name.map(String::from), range: group.range() }))
+                .collect(),
+            distance: None,
+        })
+        .collect()
+}
+
+fn fuzzy_spans(finder: &Fuzzy, pattern: usize, line: &str) -> Vec<Span> {
+    let mut spans = Vec::new();
+    let mut at = 0;
+    while at < line.len() {
+        let hit = match finder.find_at(line, at) {
+            Some(hit) if !hit.range.is_empty() => hit,
+            _ => break,
+        };
+        at = hit.range.end;
+        spans.push(Span { range: hit.range, pattern, submatches: Vec::new(), distance: Some(hit.distance) });
+    }
+    spans
+}
+
+// one pattern per line; blank lines are skipped so a stray empty line at the
+// end of a list does not match everything
+fn read_patterns(path: &str) -> io::Result<Vec<String>> {
+    let reader = encoding::decode_reader(File::open(path)?, None)?;
+    let mut patterns = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let line = line.trim_end_matches('\r');
+        if !line.is_empty() {
+            patterns.push(line.to_string());
+        }
+    }
+    Ok(patterns)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(args: &[&str]) -> PatternSet {
+        PatternSet::new(&Config::new(&crate::tests::argv(args)).unwrap()).unwrap()
+    }
+
+    fn hits(matcher: &PatternSet, line: &str) -> Vec<(String, usize)> {
+        matcher.find_spans(line).into_iter().map(|s| (line[s.range].to_string(), s.pattern)).collect()
+    }
+
+    #[test]
+    fn test_many_literals() {
+        let m = matcher(&["program_name", "-e", "200024335", "-e", "PRODUCTCODE", "-e", "苹果", "a.txt"]);
+        let line = "苹果17 productCode%3D1&activityId%3D200024335";
+        assert!(m.is_match(line));
+        assert_eq!(hits(&m, line), vec![("苹果".into(), 2), ("productCode".into(), 1), ("200024335".into(), 0)]);
+        assert!(!m.is_match("iPhone 17"));
+    }
+
+    #[test]
+    fn test_many_literals_case_sensitive_overlap() {
+        let m = matcher(&["program_name", "-e", "Pro", "-e", "Pro Max", "-s", "a.txt"]);
+        // the first pattern wins where both start at the same place
+        assert_eq!(hits(&m, "iPhone 17 Pro Max"), vec![("Pro".into(), 0)]);
+        let m = matcher(&["program_name", "-e", "ПРО", "a.txt"]);
+        assert_eq!(hits(&m, "iPhone про"), vec![("про".into(), 0)]);
+    }
+
+    #[test]
+    fn test_many_regexes() {
+        let m = matcher(&["program_name", "-E", "-e", r"productCode%3D(\d+)", "-e", r"activityId%3D(?P<id>\d+)", "a.txt"]);
+        let line = "x%3FactivityId%3D200024335 productcode%3D1";
+        let spans = m.find_spans(line);
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].pattern, 1);
+        assert_eq!(spans[0].submatches[0].as_ref().unwrap().name.as_deref(), Some("id"));
+        assert_eq!(&line[spans[1].range.clone()], "productcode%3D1");
+    }
+
+    #[test]
+    fn test_spans_map_back_to_original() {
+        let line = "苹果17单点链接：HTTPS://gd https：";
+        let m = matcher(&["program_name", "https:", "-"]);
+        assert_eq!(hits(&m, line), vec![("HTTPS:".into(), 0)]);
+        let m = matcher(&["program_name", "https:", "-", "--normalize"]);
+        assert_eq!(hits(&m, line), vec![("HTTPS:".into(), 0), ("https：".into(), 0)]);
+        let m = matcher(&["program_name", r"https:\S+", "-", "--normalize", "-E"]);
+        assert!(m.is_match("链接：ｈｔｔｐｓ：//wx"));
+        // every `f` of `ﬃ` maps back to the whole ligature, it is reported once
+        let m = matcher(&["program_name", "f", "-", "--normalize"]);
+        assert_eq!(hits(&m, "xﬃx ff"), vec![("ﬃ".into(), 0), ("f".into(), 0), ("f".into(), 0)]);
+        let m = matcher(&["program_name", "-e", "kg", "-e", "g", "-", "--normalize"]);
+        assert_eq!(hits(&m, "5㎏"), vec![("㎏".into(), 0)]);
+    }
+
+    #[test]
+    fn test_matcher_impls() {
+        let line = "【广东】苹果17 ProMax productCode%3D2025";
+        let matchers: Vec<Box<dyn Matcher>> = vec![
+            Box::new(Literal::new("ProMax")),
+            Box::new(CaseInsensitive::new("PROMAX")),
+            Box::new(Regex::new(r"[A-Z]\w+Max").unwrap()),
+            Box::new(matcher(&["program_name", "promax", "a.txt"])),
+        ];
+        for m in matchers.iter() {
+            let hit = m.find_at(line, 0).unwrap();
+            assert_eq!(&line[hit.range.clone()], "ProMax");
+            assert_eq!(hit.distance, 0);
+            assert_eq!(m.find_at(line, hit.range.end), None);
+        }
+        assert!(!Literal::new("promax").is_match(line));
+    }
+
+    #[test]
+    fn test_fuzzy_patterns() {
+        let m = matcher(&["program_name", "--fuzzy", "1", "-e", "iPhone", "-e", "productCode", "a.txt"]);
+        let line = "iphnoe 17 prodctCode%3D1 IPHONE";
+        let spans = m.find_spans(line);
+        let hits: Vec<_> = spans.iter().map(|s| (&line[s.range.clone()], s.pattern, s.distance)).collect();
+        assert_eq!(hits, vec![("prodctCode", 1, Some(1)), ("IPHONE", 0, Some(0))]);
+        assert!(!m.is_match("iphnoe 17"));
+        assert!(!m.can_find_bytes());
+    }
+
+    #[test]
+    fn test_query() {
+        let m = matcher(&["program_name", "--query", "(iphone OR 苹果) 17 NOT 广东", "a.txt"]);
+        assert_eq!(m.patterns(), ["iphone", "苹果", "17", "广东"]);
+        assert!(m.is_match("【福建】iPhone 17"));
+        assert!(!m.is_match("【广东】苹果17"));
+        assert!(!m.is_match("iPhone 16"));
+        // the negated term is not highlighted
+        assert_eq!(hits(&m, "苹果17 not 广东"), vec![("苹果".to_string(), 1), ("17".to_string(), 2)]);
+        assert!(m.is_block_match(&["【福建】", "苹果", "17"]));
+        assert!(!m.can_find_bytes());
+    }
+
+    #[test]
+    fn test_pattern_file() {
+        let path = std::env::temp_dir().join(format!("rust_demo_patterns_{}.txt", std::process::id()));
+        std::fs::write(&path, "2025090916365395401379458\r\n\n200024337\n").unwrap();
+        let m = matcher(&["program_name", "-f", path.to_str().unwrap(), "a.txt"]);
+        assert_eq!(m.patterns(), ["2025090916365395401379458", "200024337"]);
+        assert!(m.is_match("contractPhone%3FactivityId%3D200024337&net_token"));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,164 @@
+use std::io::{self, BufRead, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::matcher::{PatternSet, Span, Submatch};
+use crate::{json, Config};
+
+// -U: the whole input is searched at once so a match can run over line ends.
+// Each match prints every line it covers, each with its own line number, and
+// matches that share a line are printed as one block. Returns how many
+// matches were selected.
+pub fn search_reader<R, W>(config: &Config, matcher: &PatternSet, mut reader: R, path: Option<&Path>, out: &mut W) -> io::Result<usize>
+where
+    R: BufRead,
+    W: Write,
+{
+    let mut bytes = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    let text = String::from_utf8_lossy(&bytes);
+    let lines = LineStarts::new(&text);
+
+    let mut spans = matcher.find_spans(&text);
+    if let Some(limit) = config.limit() {
+        spans.truncate(limit);
+    }
+    let selected = spans.len();
+    if config.quiet() {
+        return Ok(selected);
+    }
+
+    if config.json {
+        for span in spans {
+            let (first, last) = lines.covered(&span.range);
+            let start = lines.start(first);
+            // a match that ends with the newline runs past the line text
+            let block = &text[start..lines.end(last).max(span.range.end)];
+            json::write_matches(out, path, first, start, block, None, &[shift(span, start)])?;
+        }
+    } else if config.only_matching {
+        for span in spans.iter() {
+            let (first, _) = lines.covered(&span.range);
+            let matched = &text[span.range.clone()];
+            for (i, piece) in matched.strip_suffix('\n').unwrap_or(matched).split('\n').enumerate() {
+                let piece = piece.strip_suffix('\r').unwrap_or(piece);
+                let whole = 0..piece.len();
+                out.write_all(config.format_line(path, first + i, piece, true, None, std::slice::from_ref(&whole)).as_bytes())?;
+            }
+        }
+    } else {
+        // (first line, last line, matches) of each block
+        let mut blocks: Vec<(usize, usize, Vec<Range<usize>>)> = Vec::new();
+        for span in spans.iter() {
+            let (first, last) = lines.covered(&span.range);
+            match blocks.last_mut() {
+                Some(block) if first <= block.1 => {
+                    block.1 = block.1.max(last);
+                    block.2.push(span.range.clone());
+                }
+                _ => blocks.push((first, last, vec![span.range.clone()])),
+            }
+        }
+        for (first, last, ranges) in blocks.iter() {
+            for index in *first..=*last {
+                let (start, end) = (lines.start(index), lines.end(index));
+                // the part of each match on this line
+                let matches: Vec<Range<usize>> = ranges
+                    .iter()
+                    .map(|range| range.start.max(start) - start..range.end.min(end).max(start) - start)
+                    .filter(|range| !range.is_empty())
+                    .collect();
+                out.write_all(config.format_line(path, index, &text[start..end], true, None, &matches).as_bytes())?;
+            }
+        }
+    }
+    Ok(selected)
+}
+
+// where each line of the input starts
+struct LineStarts<'a> {
+    text: &'a str,
+    starts: Vec<usize>,
+}
+
+impl LineStarts<'_> {
+    fn new(text: &str) -> LineStarts<'_> {
+        let starts = std::iter::once(0).chain(memchr::memchr_iter(b'\n', text.as_bytes()).map(|i| i + 1)).collect();
+        LineStarts { text, starts }
+    }
+
+    fn start(&self, index: usize) -> usize {
+        self.starts[index]
+    }
+
+    // where the text of a line ends, before its `\n` or `\r\n`
+    fn end(&self, index: usize) -> usize {
+        let end = self.starts.get(index + 1).map_or(self.text.len(), |next| next - 1);
+        if self.text[..end].ends_with('\r') { end - 1 } else { end }
+    }
+
+    fn line_of(&self, offset: usize) -> usize {
+        self.starts.partition_point(|&start| start <= offset) - 1
+    }
+
+    // the first and last line of a non-empty match
+    fn covered(&self, range: &Range<usize>) -> (usize, usize) {
+        (self.line_of(range.start), self.line_of(range.end - 1))
+    }
+}
+
+// a span of the whole input made relative to `start`
+fn shift(span: Span, start: usize) -> Span {
+    Span {
+        range: span.range.start - start..span.range.end - start,
+        submatches: span
+            .submatches
+            .into_iter()
+            .map(|sub| sub.map(|sub| Submatch { range: sub.range.start - start..sub.range.end - start, ..sub }))
+            .collect(),
+        ..span
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::run_search;
+
+
+    const INPUT: &str = "【广东】\r\n苹果17\r\npromax 到货\r\n【福建】\r\niPhone 17 Pro\r\nMax 苹果17 promax\r\n";
+
+    #[test]
+    fn test_literal_across_lines() {
+        // the space in the pattern matches the line break
+        assert_eq!(
+            run_search(&["program_name", "-U", "-n", "苹果17 promax", "-"], INPUT),
+            (2, "2: 苹果17\n3: promax 到货\n6: Max 苹果17 promax\n".to_string())
+        );
+        assert_eq!(run_search(&["program_name", "-U", "-c", "pro max", "-"], INPUT), (1, String::new()));
+    }
+
+    #[test]
+    fn test_regex_blocks() {
+        // matches sharing a line are one block, `^` matches at every line
+        assert_eq!(
+            run_search(&["program_name", "-U", "-E", "-n", r"^【.*】\r\n\w+|17 Pro\s+Max", "-"], INPUT),
+            (3, "1: 【广东】\n2: 苹果17\n4: 【福建】\n5: iPhone 17 Pro\n6: Max 苹果17 promax\n".to_string())
+        );
+        assert_eq!(
+            run_search(&["program_name", "-U", "-E", "-s", "-o", "-n", r"17\s+pro", "-"], INPUT),
+            (2, "2: 17\n3: pro\n6: 17 pro\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_json_end_line() {
+        let (selected, out) = run_search(&["program_name", "-U", "--json", "17 promax", "-"], INPUT);
+        assert_eq!(selected, 2);
+        let records: Vec<serde_json::Value> = out.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!((records[0]["line"].clone(), records[0]["end_line"].clone()), (2.into(), 3.into()));
+        assert_eq!(records[0]["text"], "17\r\npromax");
+        assert_eq!(records[0]["column"], 3);
+        assert_eq!(records[1]["line"], 6);
+        assert!(records[1].get("end_line").is_none());
+    }
+}
@@ -0,0 +1,120 @@
+use std::ops::Range;
+
+use unicode_normalization::char::{canonical_combining_class, compose};
+use unicode_normalization::UnicodeNormalization;
+
+// a line in compatibility-folded form, remembering where every byte came from
+// so a match found in `text` can be reported against the original line
+pub struct Folded {
+    pub text: String,
+    // length of the original line
+    len: usize,
+    // for each byte of `text`, the byte range of the original chars it came from
+    sources: Vec<Range<usize>>,
+}
+
+impl Folded {
+    // translate a byte range of the folded text into one of the original line
+    pub fn original_range(&self, range: Range<usize>) -> Range<usize> {
+        if range.is_empty() {
+            let at = self.sources.get(range.start).map_or(self.len, |r| r.start);
+            return at..at;
+        }
+        self.sources[range.start].start..self.sources[range.end - 1].end
+    }
+
+    // append the folded form of `segment`, every byte of it comes from `source`
+    fn push(&mut self, source: Range<usize>, segment: &str, case_sensitive: bool) {
+        let before = self.text.len();
+        for composed in segment.nfkc() {
+            if case_sensitive {
+                self.text.push(composed);
+            } else {
+                self.text.extend(composed.to_lowercase());
+            }
+        }
+        self.sources.extend(std::iter::repeat_n(source, self.text.len() - before));
+    }
+}
+
+// fold full-width / half-width forms, ligatures, circled digits and the like
+// to their plain equivalents (`https：` becomes `https:`), and optionally case.
+// Each char is folded (NFKC) together with the marks and Hangul jamo that
+// combine with it, so they stay attached: `cafe` does not match `café` and
+// `가` does not match `각`, and no byte is ever shared between two source chars.
+pub fn fold(line: &str, case_sensitive: bool) -> Folded {
+    let mut folded = Folded { text: String::with_capacity(line.len()), len: line.len(), sources: Vec::with_capacity(line.len()) };
+    let mut start = 0;
+    for (at, ch) in line.char_indices() {
+        if at > start && !combines(&line[start..at], ch) {
+            folded.push(start..at, &line[start..at], case_sensitive);
+            start = at;
+        }
+    }
+    if start < line.len() {
+        folded.push(start..line.len(), &line[start..], case_sensitive);
+    }
+    folded
+}
+
+// whether `ch` composes onto the chars before it: a combining mark, or a
+// char like a jamo that NFKC merges with the last one
+fn combines(before: &str, ch: char) -> bool {
+    // no composition takes an ASCII char second
+    if ch.is_ascii() {
+        return false;
+    }
+    if canonical_combining_class(ch) != 0 {
+        return true;
+    }
+    match (before.nfkc().last(), std::iter::once(ch).nfkd().next()) {
+        (Some(last), Some(first)) => compose(last, first).is_some(),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // byte range in `line` of the first place `query` matches after folding both
+    fn find(query: &str, line: &str, case_sensitive: bool) -> Option<Range<usize>> {
+        let query = fold(query, case_sensitive).text;
+        let line = fold(line, case_sensitive);
+        line.text.find(&query).map(|start| line.original_range(start..start + query.len()))
+    }
+
+    #[test]
+    fn test_fold_full_width() {
+        assert_eq!(fold("苹果17单点链接：https：https://gd.10086.cn", false).text, "苹果17单点链接:https:https://gd.10086.cn");
+        assert_eq!(fold("ＨＴＴＰＳ：//example", false).text, "https://example");
+        assert_eq!(fold("ＨＴＴＰＳ：//example", true).text, "HTTPS://example");
+    }
+
+    #[test]
+    fn test_find_maps_back_to_original() {
+        let line = "链接：https：ok";
+        let range = find("https:", line, false).unwrap();
+        assert_eq!(&line[range], "https：");
+        // one full-width char folds to several bytes, the range still covers it whole
+        let line = "Ａ１７ promax";
+        assert_eq!(&line[find("a17", line, false).unwrap()], "Ａ１７");
+        assert_eq!(find("ﬁ", "file", true), Some(0..2));
+        assert_eq!(find("https:", "http:", false), None);
+    }
+
+    #[test]
+    fn test_marks_stay_attached() {
+        assert_eq!(find("cafe", "café au lait", false), None);
+        assert_eq!(find("café", "café au lait", false), Some(0..5));
+        // a decomposed é matches a composed one, and is reported whole
+        let line = "cafe\u{301} au lait";
+        assert_eq!(&line[find("café", line, false).unwrap()], "cafe\u{301}");
+        assert_eq!(find("cafe", line, false), None);
+        // Hangul syllables are not split into jamo, jamo are composed
+        assert_eq!(find("가", "각", false), None);
+        let jamo = "\u{1100}\u{1161}\u{11a8}";
+        assert_eq!(find("각", jamo, false), Some(0..jamo.len()));
+        assert_eq!(find("가", jamo, false), None);
+    }
+}
@@ -0,0 +1,65 @@
+use rayon::prelude::*;
+
+// how many items are searched at once; their results wait for the slowest of
+// them, so this bounds both the memory held and the work done past an error
+const BATCH: usize = 64;
+
+// run `work` on every item on all cores and hand the results to `done` on the
+// calling thread in input order. Items go to rayon a batch at a time, and a
+// batch is handed over before the next one starts. An error from `done` stops
+// the batches and is returned.
+pub fn for_each<T, R, E, W, D>(items: &[T], work: W, mut done: D) -> Result<(), E>
+where
+    T: Sync,
+    R: Send,
+    W: Fn(&T) -> R + Sync,
+    D: FnMut(&T, R) -> Result<(), E>,
+{
+    for batch in items.chunks(BATCH) {
+        let results: Vec<R> = batch.par_iter().map(&work).collect();
+        for (item, result) in batch.iter().zip(results) {
+            done(item, result)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+
+    #[test]
+    fn test_for_each_keeps_order() {
+        let items: Vec<usize> = (0..500).collect();
+        let mut seen = Vec::new();
+        let result: Result<(), ()> = for_each(
+            &items,
+            |&i| {
+                // later items finish first
+                thread::sleep(std::time::Duration::from_micros(((500 - i) % 7) as u64 * 50));
+                i * 2
+            },
+            |&i, doubled| {
+                seen.push((i, doubled));
+                Ok(())
+            },
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(seen, items.iter().map(|&i| (i, i * 2)).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_for_each_stops_on_error() {
+        let items: Vec<usize> = (0..10_000).collect();
+        let worked = AtomicUsize::new(0);
+        let result = for_each(
+            &items,
+            |_| worked.fetch_add(1, Ordering::Relaxed),
+            |&i, _| if i == 3 { Err(i) } else { Ok(()) },
+        );
+        assert_eq!(result, Err(3));
+        assert!(worked.load(Ordering::Relaxed) < items.len());
+    }
+}
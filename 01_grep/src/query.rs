@@ -0,0 +1,220 @@
+// --query: terms combined with AND, OR, NOT and parentheses, like
+// `(iPhone OR 苹果) AND 17 NOT 广东`. NOT binds tightest, then AND, then OR;
+// terms next to each other are ANDed, and a term with spaces or parentheses
+// in it is written in double quotes. Only the uppercase words are operators.
+
+#[derive(Debug, PartialEq)]
+pub enum Expr {
+    // index into `Query::terms`
+    Term(usize),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Query {
+    pub expr: Expr,
+    // each is searched with the matcher the flags ask for, like a -e pattern
+    pub terms: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Term(String),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+impl Expr {
+    // `has(term)` says whether a term is found, it is only asked when needed
+    pub fn eval<F: Fn(usize) -> bool>(&self, has: &F) -> bool {
+        match self {
+            Expr::Term(term) => has(*term),
+            Expr::Not(expr) => !expr.eval(has),
+            Expr::And(left, right) => left.eval(has) && right.eval(has),
+            Expr::Or(left, right) => left.eval(has) || right.eval(has),
+        }
+    }
+
+    // mark the terms that count for the match when found: those under an even
+    // number of NOTs; a found NOT term is not worth highlighting
+    pub fn positive(&self, negated: bool, marks: &mut [bool]) {
+        match self {
+            Expr::Term(term) => marks[*term] |= !negated,
+            Expr::Not(expr) => expr.positive(!negated, marks),
+            Expr::And(left, right) | Expr::Or(left, right) => {
+                left.positive(negated, marks);
+                right.positive(negated, marks);
+            }
+        }
+    }
+}
+
+pub fn parse(text: &str) -> Result<Query, String> {
+    let mut parser = Parser { tokens: tokenize(text)?, pos: 0, terms: Vec::new() };
+    if parser.tokens.is_empty() {
+        return Err(String::from("the query is empty"));
+    }
+    let expr = parser.or()?;
+    if parser.pos < parser.tokens.len() {
+        return Err(String::from("unexpected `)`"));
+    }
+    Ok(Query { expr, terms: parser.terms })
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            _ if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' | ')' => {
+                chars.next();
+                tokens.push(if c == '(' { Token::Open } else { Token::Close });
+            }
+            '"' => {
+                chars.next();
+                let mut phrase = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => phrase.push(c),
+                        None => return Err(format!("unclosed quote before `{}`", phrase)),
+                    }
+                }
+                tokens.push(Token::Term(phrase));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = chars.peek().filter(|&&c| !c.is_whitespace() && !matches!(c, '(' | ')' | '"')) {
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(match word.as_str() {
+                    "AND" => Token::And,
+                    "OR" => Token::Or,
+                    "NOT" => Token::Not,
+                    _ => Token::Term(word),
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    terms: Vec<String>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.not()?;
+        loop {
+            match self.peek() {
+                Some(Token::And) => self.pos += 1,
+                // `苹果 17` and `promax NOT 广东` are ANDs too
+                Some(Token::Term(_) | Token::Not | Token::Open) => {}
+                _ => return Ok(left),
+            }
+            left = Expr::And(Box::new(left), Box::new(self.not()?));
+        }
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.peek() == Some(&Token::Not) {
+            self.pos += 1;
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        match token {
+            Some(Token::Term(term)) => {
+                self.terms.push(term.clone());
+                Ok(Expr::Term(self.terms.len() - 1))
+            }
+            Some(Token::Open) => {
+                let expr = self.or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(String::from("missing `)`"));
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            Some(Token::Close) => Err(String::from("unexpected `)`")),
+            Some(Token::And | Token::Or | Token::Not) | None => Err(String::from("AND, OR and NOT need a term on each side")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(index: usize) -> Box<Expr> {
+        Box::new(Expr::Term(index))
+    }
+
+    #[test]
+    fn test_parse() {
+        let query = parse("苹果 AND promax NOT 广东").unwrap();
+        assert_eq!(query.terms, vec!["苹果", "promax", "广东"]);
+        assert_eq!(query.expr, Expr::And(Box::new(Expr::And(term(0), term(1))), Box::new(Expr::Not(term(2)))));
+
+        let query = parse("(iPhone OR 苹果) AND 17").unwrap();
+        assert_eq!(query.expr, Expr::And(Box::new(Expr::Or(term(0), term(1))), term(2)));
+
+        // AND binds tighter than OR, lowercase words are terms
+        let query = parse("a OR b c and").unwrap();
+        assert_eq!(query.terms, vec!["a", "b", "c", "and"]);
+        assert_eq!(query.expr, Expr::Or(term(0), Box::new(Expr::And(Box::new(Expr::And(term(1), term(2))), term(3)))));
+
+        let query = parse(r#""Pro Max" NOT "(old)""#).unwrap();
+        assert_eq!(query.terms, vec!["Pro Max", "(old)"]);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for text in ["", "   ", "苹果 AND", "OR 17", "(苹果 OR 17", "苹果)", "NOT", "\"Pro Max"] {
+            assert!(parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_eval() {
+        let query = parse("(iPhone OR 苹果) AND 17 NOT 广东").unwrap();
+        let line = |text: &str| query.expr.eval(&|term| text.contains(query.terms[term].as_str()));
+        assert!(line("【福建】苹果17"));
+        assert!(line("iPhone 17"));
+        assert!(!line("【广东】苹果17"));
+        assert!(!line("苹果16"));
+
+        let mut marks = vec![false; query.terms.len()];
+        query.expr.positive(false, &mut marks);
+        assert_eq!(marks, vec![true, true, true, false]);
+    }
+}
@@ -0,0 +1,205 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use encoding_rs::Encoding;
+use regex::Regex;
+use tempfile::NamedTempFile;
+
+use crate::encoding;
+
+// a line that the replacement changed, `index` is 0-based
+#[derive(Debug, PartialEq)]
+pub struct Change {
+    pub index: usize,
+    pub old: String,
+    pub new: String,
+}
+
+// run the replacement over every line of `text`; the template takes `$1`,
+// `${name}` and `$0` like `Regex::replace_all`. Line endings are kept as they were.
+pub fn replace_text(re: &Regex, template: &str, text: &str) -> (String, Vec<Change>) {
+    let mut result = String::with_capacity(text.len());
+    let mut changes = Vec::new();
+    for (index, line) in text.split_inclusive('\n').enumerate() {
+        let content = line.trim_end_matches(['\n', '\r']);
+        let ending = &line[content.len()..];
+        let replaced = re.replace_all(content, template);
+        if replaced != content {
+            changes.push(Change { index, old: content.to_string(), new: replaced.to_string() });
+        }
+        result.push_str(&replaced);
+        result.push_str(ending);
+    }
+    (result, changes)
+}
+
+// a unified diff with no context lines, runs of changed lines share a hunk
+pub fn unified_diff(name: &str, changes: &[Change]) -> String {
+    let mut diff = String::new();
+    if changes.is_empty() {
+        return diff;
+    }
+    let _ = writeln!(diff, "--- {}\n+++ {}", name, name);
+    let mut start = 0;
+    while start < changes.len() {
+        let mut end = start + 1;
+        while end < changes.len() && changes[end].index == changes[end - 1].index + 1 {
+            end += 1;
+        }
+        let hunk = &changes[start..end];
+        let line = hunk[0].index + 1;
+        let _ = writeln!(diff, "@@ -{},{} +{},{} @@", line, hunk.len(), line, hunk.len());
+        for change in hunk.iter() {
+            let _ = writeln!(diff, "-{}", change.old);
+        }
+        for change in hunk.iter() {
+            let _ = writeln!(diff, "+{}", change.new);
+        }
+        start = end;
+    }
+    diff
+}
+
+// replace in one file: returns the preview diff, or an empty string after
+// rewriting the file when `in_place` is set, and whether anything changed
+pub fn replace_file(re: &Regex, template: &str, path: &Path, forced: Option<&'static Encoding>, in_place: bool, backup: bool) -> io::Result<(String, bool)> {
+    let mut bytes = Vec::new();
+    fs::File::open(path)?.read_to_end(&mut bytes)?;
+
+    let detected = forced.unwrap_or_else(|| encoding::detect(&bytes));
+    // only encodings we can write back again, encoding_rs has no UTF-16 encoder
+    if in_place && detected.output_encoding() != detected {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("cannot rewrite {} files in place", detected.name())));
+    }
+    let bom_len = match Encoding::for_bom(&bytes) {
+        Some((bom_encoding, len)) if bom_encoding == detected => len,
+        _ => 0,
+    };
+    let (bom, body) = bytes.split_at(bom_len);
+    let text = detected.decode_without_bom_handling(body).0;
+
+    let (_, changes) = replace_text(re, template, &text);
+    if changes.is_empty() {
+        return Ok((String::new(), false));
+    }
+    if !in_place {
+        return Ok((unified_diff(&path.display().to_string(), &changes), true));
+    }
+
+    let mut output = bom.to_vec();
+    output.extend_from_slice(&splice(detected, body, &changes)?);
+    write_atomic(path, &output, backup)?;
+    Ok((String::new(), true))
+}
+
+// the original bytes with only the changed lines encoded again, so a byte the
+// decoder had to replace on some other line is written back untouched; a
+// changed line that did not decode, or does not encode, cleanly is refused
+fn splice(encoding: &'static Encoding, body: &[u8], changes: &[Change]) -> io::Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(body.len());
+    let mut changes = changes.iter().peekable();
+    // `\n` is a single byte in every encoding we can write, like the split in `replace_text`
+    for (index, line) in body.split_inclusive(|&b| b == b'\n').enumerate() {
+        let Some(change) = changes.next_if(|change| change.index == index) else {
+            output.extend_from_slice(line);
+            continue;
+        };
+        let content_len = line.iter().rposition(|&b| b != b'\n' && b != b'\r').map_or(0, |i| i + 1);
+        let (new, _, unmappable) = encoding.encode(&change.new);
+        if encoding.decode_without_bom_handling_and_without_replacement(&line[..content_len]).is_none() || unmappable {
+            let message = format!("line {} is not valid {}, not rewriting it", index + 1, encoding.name());
+            return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+        }
+        output.extend_from_slice(&new);
+        output.extend_from_slice(&line[content_len..]);
+    }
+    Ok(output)
+}
+
+// write to a temp file next to `path` and rename it over the original, so a
+// crash never leaves a half written file; the original is copied to `.bak` first
+fn write_atomic(path: &Path, contents: &[u8], backup: bool) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut temp = NamedTempFile::new_in(dir)?;
+    temp.write_all(contents)?;
+    temp.as_file().sync_all()?;
+    fs::set_permissions(temp.path(), fs::metadata(path)?.permissions())?;
+
+    if backup {
+        let mut backup_path = path.as_os_str().to_owned();
+        backup_path.push(".bak");
+        fs::copy(path, backup_path)?;
+    }
+    temp.persist(path).map_err(|err| err.error)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::GB18030;
+
+    #[test]
+    fn test_replace_text_groups_and_endings() {
+        let re = Regex::new(r"productCode%3D(\d+)").unwrap();
+        let text = "【通用】\r\na%3FproductCode%3D2025&x\nnone\nproductCode%3D1";
+        let (replaced, changes) = replace_text(&re, "code=${1}", text);
+        assert_eq!(replaced, "【通用】\r\na%3Fcode=2025&x\nnone\ncode=1");
+        assert_eq!(changes.iter().map(|c| c.index).collect::<Vec<_>>(), vec![1, 3]);
+    }
+
+    #[test]
+    fn test_unified_diff_hunks() {
+        let re = Regex::new("17").unwrap();
+        let (_, changes) = replace_text(&re, "18", "苹果17\niPhone 17\nx\n17");
+        assert_eq!(
+            unified_diff("a.txt", &changes),
+            "--- a.txt\n+++ a.txt\n@@ -1,2 +1,2 @@\n-苹果17\n-iPhone 17\n+苹果18\n+iPhone 18\n@@ -4,1 +4,1 @@\n-17\n+18\n"
+        );
+    }
+
+    #[test]
+    fn test_replace_file_in_place() {
+        let dir = std::env::temp_dir().join(format!("rust_demo_replace_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("gbk.txt");
+        fs::write(&path, GB18030.encode("【广东】苹果17\n").0).unwrap();
+        let re = Regex::new("苹果").unwrap();
+
+        let (diff, changed) = replace_file(&re, "iPhone ", &path, None, false, false).unwrap();
+        assert!(changed);
+        assert!(diff.contains("+【广东】iPhone 17"));
+
+        replace_file(&re, "iPhone ", &path, None, true, true).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), GB18030.encode("【广东】iPhone 17\n").0.to_vec());
+        assert_eq!(fs::read(dir.join("gbk.txt.bak")).unwrap(), GB18030.encode("【广东】苹果17\n").0.to_vec());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_replace_file_keeps_undecodable_bytes() {
+        let dir = std::env::temp_dir().join(format!("rust_demo_replace_bytes_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("mixed.txt");
+        let re = Regex::new("foo").unwrap();
+
+        // the stray Latin-1 byte on the unchanged line stays as it was
+        fs::write(&path, b"\xe8\x8b\xb9\xe6\x9e\x9c foo\nlatin1 caf\xe9\n").unwrap();
+        let (diff, _) = replace_file(&re, "bar", &path, None, false, false).unwrap();
+        assert!(diff.contains("+苹果 bar"), "{}", diff);
+        replace_file(&re, "bar", &path, None, true, false).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"\xe8\x8b\xb9\xe6\x9e\x9c bar\nlatin1 caf\xe9\n");
+
+        // on a changed line it would be lost, so the file is left alone
+        fs::write(&path, b"foo caf\xe9\r\n").unwrap();
+        let err = replace_file(&re, "bar", &path, encoding::for_label("utf-8"), true, false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(fs::read(&path).unwrap(), b"foo caf\xe9\r\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
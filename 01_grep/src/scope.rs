@@ -0,0 +1,158 @@
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use clap::ValueEnum;
+
+use crate::matcher::PatternSet;
+use crate::search::trim_newline;
+use crate::{color, json, Config};
+
+// --scope
+#[derive(Debug, Default, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Scope {
+    #[default]
+    Line,
+    // lines up to a blank line
+    Paragraph,
+    // a --section heading and the lines up to the next one
+    Section,
+}
+
+// a line of the block being read: index, input offset, text
+type Line = (usize, usize, String);
+
+// --scope paragraph / section: the patterns, or the --query, are tried on
+// whole blocks, and a block that matches is printed whole with `--` between
+// blocks. -c counts blocks and -m limits them. Only one block is held at a time.
+pub fn search_reader<R, W>(config: &Config, matcher: &PatternSet, mut reader: R, path: Option<&Path>, out: &mut W) -> io::Result<usize>
+where
+    R: BufRead,
+    W: Write,
+{
+    let limit = config.limit();
+    let mut blocks = Blocks { config, matcher, path, selected: 0, printed: false, in_section: config.in_section.is_empty() };
+    let mut block: Vec<Line> = Vec::new();
+    // whether the block's first line was in an --in-section section
+    let mut block_in_section = blocks.in_section;
+
+    let mut buf = Vec::new();
+    let mut index = 0;
+    let mut offset = 0;
+    while limit.is_none_or(|limit| blocks.selected < limit) {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        let text = String::from_utf8_lossy(trim_newline(&buf)).into_owned();
+        let heading = matcher.is_heading(&text);
+        if heading {
+            blocks.in_section = config.in_section.is_empty() || config.in_section.iter().any(|s| text.contains(s.as_str()));
+        }
+        let blank = config.scope == Scope::Paragraph && text.trim().is_empty();
+        if blank || heading && config.scope == Scope::Section {
+            blocks.flush(&mut block, block_in_section, out)?;
+        }
+        if !blank {
+            if block.is_empty() {
+                block_in_section = blocks.in_section;
+            }
+            block.push((index, offset, text));
+        }
+        index += 1;
+        offset += buf.len();
+    }
+    if limit.is_none_or(|limit| blocks.selected < limit) {
+        blocks.flush(&mut block, block_in_section, out)?;
+    }
+    Ok(blocks.selected)
+}
+
+struct Blocks<'a> {
+    config: &'a Config,
+    matcher: &'a PatternSet,
+    path: Option<&'a Path>,
+    selected: usize,
+    // whether a block was printed, the next one gets a `--` first
+    printed: bool,
+    // --in-section: whether the last heading seen is one of them
+    in_section: bool,
+}
+
+impl Blocks<'_> {
+    fn flush<W: Write>(&mut self, block: &mut Vec<Line>, in_section: bool, out: &mut W) -> io::Result<()> {
+        if block.is_empty() {
+            return Ok(());
+        }
+        let (config, matcher, path) = (self.config, self.matcher, self.path);
+        let texts: Vec<&str> = block.iter().map(|(_, _, text)| text.as_str()).collect();
+        if !in_section || matcher.is_block_match(&texts) == config.invert {
+            block.clear();
+            return Ok(());
+        }
+        self.selected += 1;
+
+        if config.quiet() {
+            // only counted, search_file reports the total
+        } else if config.json {
+            for (index, offset, text) in block.iter() {
+                json::write_matches(out, path, *index, *offset, text, None, &matcher.find_spans(text))?;
+            }
+        } else if config.only_matching {
+            for (index, _, text) in block.iter() {
+                for span in matcher.find_spans(text) {
+                    let whole = 0..span.range.len();
+                    out.write_all(config.format_line(path, *index, &text[span.range], true, None, std::slice::from_ref(&whole)).as_bytes())?;
+                }
+            }
+        } else {
+            if self.printed {
+                writeln!(out, "{}", config.paint(color::SEPARATOR, "--"))?;
+            }
+            // lines with a term in them get `:`, the rest of the block `-`
+            for (index, _, text) in block.iter() {
+                let has_term = !matcher.find_spans(text).is_empty();
+                let matches = config.highlights(matcher, text);
+                out.write_all(config.format_line(path, *index, text, has_term, None, &matches).as_bytes())?;
+            }
+            self.printed = true;
+        }
+        block.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::run_search;
+
+
+    const INPUT: &str = "【广东】\n苹果17\npromax 到货\n\n【福建】\niPhone 17 Pro Max\n\n【通用】\n苹果16\n17 promax\n";
+
+    #[test]
+    fn test_paragraphs() {
+        // the terms may be on different lines of a paragraph
+        assert_eq!(
+            run_search(&["program_name", "--query", "苹果 AND promax NOT 广东", "--scope", "paragraph", "-n", "-"], INPUT),
+            (1, "8- 【通用】\n9: 苹果16\n10: 17 promax\n".to_string())
+        );
+        assert_eq!(
+            run_search(&["program_name", "--query", "(iPhone OR 苹果) AND 17", "--scope", "paragraph", "-"], INPUT),
+            (3, "【广东】\n苹果17\npromax 到货\n--\n【福建】\niPhone 17 Pro Max\n--\n【通用】\n苹果16\n17 promax\n".to_string())
+        );
+        assert_eq!(run_search(&["program_name", "promax", "--scope", "paragraph", "-c", "-v", "-"], INPUT), (1, String::new()));
+    }
+
+    #[test]
+    fn test_sections() {
+        let input = INPUT.replace("\n\n", "\n");
+        assert_eq!(
+            run_search(&["program_name", "--query", "NOT 苹果", "--scope", "section", "--section", "^【", "-"], &input),
+            (1, "【福建】\niPhone 17 Pro Max\n".to_string())
+        );
+        assert_eq!(
+            run_search(&["program_name", "--query", "苹果 promax", "--scope", "section", "--section", "^【", "--in-section", "通用", "-n", "-"], &input),
+            (1, "6- 【通用】\n7: 苹果16\n8: 17 promax\n".to_string())
+        );
+        assert_eq!(run_search(&["program_name", "17", "--scope", "section", "--section", "^【", "-m", "2", "-c", "-"], &input), (2, String::new()));
+    }
+}
@@ -0,0 +1,412 @@
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use crate::context::{Context, Event};
+use crate::matcher::{PatternSet, Span};
+use crate::{color, dates, json, urls, Config};
+
+// lines are read this many bytes at a time when matches are looked for in
+// the raw buffer, so lines that cannot match are never decoded or split
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// search `reader` and write the selected lines to `out`; only a bounded amount
+// of input (one chunk, or one line plus the requested context) is held at a
+// time, so input of any size works, and bytes that are not UTF-8 are replaced
+// instead of failing the search. Returns how many lines were selected.
+pub fn search_reader<R, W>(config: &Config, matcher: &PatternSet, reader: R, path: Option<&Path>, out: &mut W) -> io::Result<usize>
+where
+    R: BufRead,
+    W: Write,
+{
+    let quiet = config.quiet();
+    let context = if quiet || config.only_matching {
+        Context::new(0, 0)
+    } else {
+        Context::new(config.before_context, config.after_context)
+    };
+    let mut lines = Lines {
+        config,
+        matcher,
+        path,
+        quiet,
+        limit: config.limit(),
+        context,
+        selected: 0,
+        section: None,
+        section_shown: false,
+        in_section: config.in_section.is_empty(),
+        in_window: config.logged.is_unbounded(),
+        rows: Vec::new(),
+    };
+
+    // without context, -v, sections or a log window every line that is not a
+    // match is skipped anyway
+    let every_line = config.invert || lines.context.has_context() || config.section.is_some() || !config.logged.is_unbounded();
+    if matcher.can_find_bytes() && !every_line {
+        search_chunks(&mut lines, reader, out)?;
+    } else {
+        search_lines(&mut lines, reader, out)?;
+    }
+
+    // --urls: the table needs every row to size its columns
+    if !lines.rows.is_empty() {
+        if let Some(path) = path {
+            writeln!(out, "{}", config.paint(color::PATH, &path.display().to_string()))?;
+        }
+        let header: &[&str] = if config.section.is_some() { &["LINE", "SECTION", "URL", "PARAM", "VALUE"] } else { &["LINE", "URL", "PARAM", "VALUE"] };
+        urls::write_table(out, header, &lines.rows)?;
+    }
+    Ok(lines.selected)
+}
+
+// the per line part of the search, shared by both ways of reading the input
+struct Lines<'a> {
+    config: &'a Config,
+    matcher: &'a PatternSet,
+    path: Option<&'a Path>,
+    quiet: bool,
+    limit: Option<usize>,
+    context: Context,
+    selected: usize,
+    // --section: the last heading (line index, text), whether it was printed
+    // yet, and whether its section is one of the --in-section ones
+    section: Option<(usize, String)>,
+    section_shown: bool,
+    in_section: bool,
+    // --since / --until: whether the last timestamp seen is in the window
+    in_window: bool,
+    // --urls: table rows, printed once the input is done
+    rows: Vec<Vec<String>>,
+}
+
+impl Lines<'_> {
+    // stop reading once -m is used up and the trailing context is out
+    fn done(&self) -> bool {
+        self.limit.is_some_and(|limit| self.selected >= limit) && !self.context.wants_more()
+    }
+
+    // `raw` is the line with its terminator, `offset` where it starts in the input
+    fn line<W: Write>(&mut self, index: usize, offset: usize, raw: &[u8], out: &mut W) -> io::Result<()> {
+        let (config, matcher, path) = (self.config, self.matcher, self.path);
+        let text = String::from_utf8_lossy(trim_newline(raw));
+        if matcher.is_heading(&text) {
+            // a heading is never a match, it is printed with the first match under it
+            self.in_section = config.in_section.is_empty() || config.in_section.iter().any(|s| text.contains(s.as_str()));
+            self.section = Some((index, text.into_owned()));
+            self.section_shown = false;
+            return Ok(());
+        }
+        if !config.logged.is_unbounded() {
+            if let Some(time) = dates::line_timestamp(&text) {
+                self.in_window = config.logged.contains(time);
+            }
+        }
+        let mut is_match = self.in_section && self.in_window && matcher.is_match(&text) != config.invert;
+        // --urls: a line only counts with a link that passes the --param filters
+        let mut links = Vec::new();
+        if is_match && config.urls {
+            links = urls::select(urls::find_links(&text), &config.params);
+            is_match = !links.is_empty();
+        }
+        // past the limit a match only counts as context
+        let is_selected = is_match && self.limit.is_none_or(|limit| self.selected < limit);
+        if is_selected {
+            self.selected += 1;
+        }
+
+        if self.quiet {
+            // only counted, search_file reports the total
+        } else if config.urls {
+            if is_selected && config.json {
+                let section = self.section.as_ref().map(|(_, heading)| heading.as_str());
+                json::write_links(out, path, index, section, &links)?;
+            } else if is_selected {
+                self.add_rows(index, &links);
+            }
+        } else if config.json {
+            if is_selected {
+                // an inverted line has no match in it, report the whole line
+                let spans = if config.invert {
+                    vec![Span { range: 0..text.len(), pattern: 0, submatches: Vec::new(), distance: None }]
+                } else {
+                    matcher.find_spans(&text)
+                };
+                let section = self.section.as_ref().map(|(_, heading)| heading.as_str());
+                json::write_matches(out, path, index, offset, &text, section, &spans)?;
+            }
+        } else if config.only_matching {
+            if is_selected && !config.invert {
+                self.show_section(out)?;
+                for span in matcher.find_spans(&text) {
+                    // the whole of the printed text is the match
+                    let whole = 0..span.range.len();
+                    let line = config.format_line(path, index, &text[span.range], true, span.distance, std::slice::from_ref(&whole));
+                    out.write_all(line.as_bytes())?;
+                }
+            }
+        } else {
+            // --fuzzy: a line is as close as its best hit
+            let distance = if is_selected && !config.invert {
+                matcher.find_spans(&text).iter().filter_map(|span| span.distance).min()
+            } else {
+                None
+            };
+            // context lines are never highlighted
+            let matches = if is_selected { config.highlights(matcher, &text) } else { Vec::new() };
+            if is_selected {
+                self.show_section(out)?;
+            }
+            let mut result = Ok(());
+            self.context.push(index, &text, is_selected, |event| {
+                if result.is_err() {
+                    return;
+                }
+                result = match event {
+                    Event::Line(line) => {
+                        let (distance, matches) = if line.index == index { (distance, matches.as_slice()) } else { (None, &[][..]) };
+                        out.write_all(config.format_line(path, line.index, line.text, line.is_match, distance, matches).as_bytes())
+                    }
+                    Event::Break => writeln!(out, "{}", config.paint(color::SEPARATOR, "--")),
+                };
+            });
+            result?;
+        }
+        Ok(())
+    }
+
+    // one table row per parameter, or just the link when it has none
+    fn add_rows(&mut self, index: usize, links: &[urls::Link]) {
+        for link in links.iter() {
+            let mut row = vec![(index + 1).to_string()];
+            if self.config.section.is_some() {
+                row.push(self.section.as_ref().map_or(String::new(), |(_, heading)| heading.clone()));
+            }
+            row.push(link.base.clone());
+            if link.params.is_empty() {
+                self.rows.push(row);
+                continue;
+            }
+            for param in link.params.iter() {
+                let mut row = row.clone();
+                row.extend([param.path.clone(), param.value.clone()]);
+                self.rows.push(row);
+            }
+        }
+    }
+
+    // print the heading of the current section before its first match
+    fn show_section<W: Write>(&mut self, out: &mut W) -> io::Result<()> {
+        if let Some((index, heading)) = self.section.as_ref().filter(|_| !self.section_shown) {
+            out.write_all(self.config.format_line(self.path, *index, heading, false, None, &[]).as_bytes())?;
+            self.section_shown = true;
+        }
+        Ok(())
+    }
+}
+
+// every line goes through the matcher
+fn search_lines<R: BufRead, W: Write>(lines: &mut Lines, mut reader: R, out: &mut W) -> io::Result<()> {
+    let mut buf = Vec::new();
+    let mut index = 0;
+    let mut offset = 0;
+    while !lines.done() {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+            break;
+        }
+        lines.line(index, offset, &buf, out)?;
+        index += 1;
+        offset += buf.len();
+    }
+    Ok(())
+}
+
+// the matcher scans whole chunks of complete lines, only the lines around its
+// hits are cut out; line numbers are kept by counting newlines with memchr
+fn search_chunks<R: BufRead, W: Write>(lines: &mut Lines, mut reader: R, out: &mut W) -> io::Result<()> {
+    let mut buf: Vec<u8> = Vec::with_capacity(2 * CHUNK_SIZE);
+    // line number and input offset of buf[0]
+    let mut index = 0;
+    let mut base = 0;
+    let mut eof = false;
+
+    while !lines.done() {
+        // fill up to a chunk, ending on a newline unless the input ended
+        while !eof && (buf.len() < CHUNK_SIZE || memchr::memrchr(b'\n', &buf).is_none()) {
+            let data = reader.fill_buf()?;
+            if data.is_empty() {
+                eof = true;
+                break;
+            }
+            buf.extend_from_slice(data);
+            let read = data.len();
+            reader.consume(read);
+        }
+        if buf.is_empty() {
+            break;
+        }
+        let end = if eof { buf.len() } else { memchr::memrchr(b'\n', &buf).unwrap() + 1 };
+
+        let mut pos = 0;
+        while pos < end && !lines.done() {
+            let hit = match lines.matcher.find_bytes(&buf[pos..end]) {
+                Some(hit) => pos + hit,
+                None => break,
+            };
+            let start = memchr::memrchr(b'\n', &buf[pos..hit]).map_or(pos, |i| pos + i + 1);
+            let stop = memchr::memchr(b'\n', &buf[hit..end]).map_or(end, |i| hit + i + 1);
+            index += memchr::memchr_iter(b'\n', &buf[pos..start]).count();
+            lines.line(index, base + start, &buf[start..stop], out)?;
+            index += 1;
+            pos = stop;
+        }
+        index += memchr::memchr_iter(b'\n', &buf[pos..end]).count();
+        buf.drain(..end);
+        base += end;
+    }
+    Ok(())
+}
+
+// drop the line terminator, `\n` or `\r\n` like `str::lines`
+pub fn trim_newline(line: &[u8]) -> &[u8] {
+    let line = line.strip_suffix(b"\n").unwrap_or(line);
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{argv, run_search};
+
+    fn config(args: &[&str]) -> Config {
+        Config::new(&argv(args)).unwrap()
+    }
+
+    #[test]
+    fn test_search_reader_non_utf8() {
+        let config = config(&["program_name", "rust", "-", "-n"]);
+        let input: &[u8] = b"\xff\xfe rust\r\nsafe\nTrust me.";
+        let mut out = Vec::new();
+        search_reader(&config, &PatternSet::new(&config).unwrap(), input, None, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "1: \u{fffd}\u{fffd} rust\n3: Trust me.\n");
+    }
+
+    #[test]
+    fn test_search_reader_json() {
+        let config = config(&["program_name", "17", "-", "--json"]);
+        let input: &[u8] = "【广东】
+苹果17 17
+".as_bytes();
+        let mut out = Vec::new();
+        search_reader(&config, &PatternSet::new(&config).unwrap(), input, Some(Path::new("a.txt")), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<serde_json::Value> = out.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["path"], "a.txt");
+        assert_eq!(lines[0]["line"], 2);
+        assert_eq!(lines[0]["column"], 3);
+        assert_eq!(lines[0]["offset"], "【广东】\n苹果".len());
+        assert_eq!(lines[1]["column"], 6);
+        assert_eq!(lines[1]["text"], "17");
+    }
+
+    #[test]
+    fn test_search_reader_context() {
+        let config = config(&["program_name", "17", "-", "-B", "1"]);
+        let input: &[u8] = "【广东】\n苹果17\n\n【福建】\niPhone 17\n".as_bytes();
+        let mut out = Vec::new();
+        search_reader(&config, &PatternSet::new(&config).unwrap(), input, None, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "【广东】\n苹果17\n--\n【福建】\niPhone 17\n");
+    }
+
+
+    #[test]
+    fn test_search_reader_chunks() {
+        // enough lines that the matches land in different chunks
+        let mut input = String::new();
+        for i in 0..20_000 {
+            input.push_str(if i % 7_000 == 6_999 { "苹果 iPhone 17 Pro\n" } else { "【广东】 nothing to see here\n" });
+        }
+        input.push_str("last line iphone");
+        let (selected, out) = run_search(&["program_name", "IPHONE", "-", "-n"], &input);
+        assert_eq!(selected, 3);
+        assert_eq!(out, "7000: 苹果 iPhone 17 Pro\n14000: 苹果 iPhone 17 Pro\n20001: last line iphone\n");
+        let (selected, out) = run_search(&["program_name", "苹果", "-", "-n", "-m", "1"], &input);
+        assert_eq!((selected, out.as_str()), (1, "7000: 苹果 iPhone 17 Pro\n"));
+    }
+
+    #[test]
+    fn test_search_reader_modes() {
+        let input = "【广东】\n苹果17 promax 苹果\n\n【福建】\niPhone 17\n";
+        assert_eq!(run_search(&["program_name", "17", "-", "-v", "-n"], input), (3, "1: 【广东】\n3: \n4: 【福建】\n".to_string()));
+        assert_eq!(run_search(&["program_name", "17", "-", "-c"], input), (2, String::new()));
+        assert_eq!(run_search(&["program_name", "苹果", "-", "-o", "-n"], input), (1, "2: 苹果\n2: 苹果\n".to_string()));
+        assert_eq!(run_search(&["program_name", "17", "-", "-m", "1"], input), (1, "苹果17 promax 苹果\n".to_string()));
+        assert_eq!(run_search(&["program_name", "17", "-", "-m", "1", "-A", "2"], input), (1, "苹果17 promax 苹果\n\n【福建】\n".to_string()));
+        assert_eq!(run_search(&["program_name", "17", "-", "-m", "0"], input), (0, String::new()));
+    }
+
+    #[test]
+    fn test_search_reader_fuzzy() {
+        let input = "【广东】苹果17ProMax\n【福建】苹果17 Pro Max\n苹果16promx\n";
+        assert_eq!(
+            run_search(&["program_name", "--fuzzy", "2", "苹果17promax", "-", "-n"], input),
+            (3, "1: ~0 【广东】苹果17ProMax\n2: ~2 【福建】苹果17 Pro Max\n3: ~2 苹果16promx\n".to_string())
+        );
+        assert_eq!(
+            run_search(&["program_name", "--fuzzy", "1", "promax", "-", "-o"], input),
+            (3, "~0 ProMax\n~1 Pro Max\n~1 promx\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_search_reader_sections() {
+        let input = "苹果 before any section\n【广东】\n苹果17\n苹果17promax\n\n【福建】\niPhone 17\n【通用】\n苹果17\n";
+        assert_eq!(
+            run_search(&["program_name", "苹果", "-", "-n", "--section", "^【.*】$"], input),
+            (4, "1: 苹果 before any section\n2- 【广东】\n3: 苹果17\n4: 苹果17promax\n8- 【通用】\n9: 苹果17\n".to_string())
+        );
+        // headings are not matches themselves, sections without a match print nothing
+        assert_eq!(
+            run_search(&["program_name", "17", "-", "--section", "^【", "--in-section", "福建", "--in-section", "通用"], input),
+            (2, "【福建】\niPhone 17\n【通用】\n苹果17\n".to_string())
+        );
+        assert_eq!(run_search(&["program_name", "广东", "-", "--section", "^【", "-c"], input), (0, String::new()));
+    }
+
+    #[test]
+    fn test_search_reader_urls() {
+        let input = "【福建】\niPhone 17：https://wx.10086.cn/sso?back=https%3A%2F%2Fwx.10086.cn%2Fphone%3FactivityId%3D200024335&t=1\nno link 17\nhttp://a.cn/x\n";
+        let (selected, out) = run_search(&["program_name", "17", "-", "--urls", "--param", "activityId"], input);
+        assert_eq!(selected, 1);
+        assert_eq!(out, "LINE  URL                      PARAM            VALUE\n2     https://wx.10086.cn/sso  back.activityId  200024335\n");
+        let (selected, out) = run_search(&["program_name", "", "-", "--urls", "--section", "^【", "--param", "activityId=1"], input);
+        assert_eq!((selected, out.as_str()), (0, ""));
+        let (selected, out) = run_search(&["program_name", "a.cn", "-", "--urls", "--json"], input);
+        assert_eq!(selected, 1);
+        assert_eq!(out, "{\"path\":null,\"line\":4,\"url\":\"http://a.cn/x\",\"base\":\"http://a.cn/x\",\"params\":[]}\n");
+    }
+
+    #[test]
+    fn test_search_reader_color() {
+        let input = "【广东】\n苹果17 苹果\n";
+        assert_eq!(
+            run_search(&["program_name", "苹果", "-", "-n", "-B", "1", "--color=always"], input).1,
+            "\x1b[32m1\x1b[0m\x1b[36m-\x1b[0m 【广东】\n\x1b[32m2\x1b[0m\x1b[36m:\x1b[0m \x1b[1;31m苹果\x1b[0m17 \x1b[1;31m苹果\x1b[0m\n"
+        );
+        // auto is settled by `run`, until then it does not colour
+        assert_eq!(run_search(&["program_name", "苹果", "-", "--color"], input).1, "苹果17 苹果\n");
+        assert_eq!(run_search(&["program_name", "苹果", "-", "-v", "--color=always"], input).1, "【广东】\n");
+    }
+
+    #[test]
+    fn test_search_reader_log_window() {
+        let input = "boot\n2025-09-09 08:00:00 ERROR 苹果17 sold out\n2025-09-09 12:00:00 ERROR timeout\n    at shop::order\n[2025-09-10 00:00:01] ERROR 苹果17\n";
+        assert_eq!(
+            run_search(&["program_name", "ERROR|at ", "-E", "-", "-n", "--since", "2025-09-09 10:00", "--until", "2025-09-10"], input),
+            (2, "3: 2025-09-09 12:00:00 ERROR timeout\n4:     at shop::order\n".to_string())
+        );
+        assert_eq!(run_search(&["program_name", "苹果", "-", "-c", "--since", "2025-09-09"], input), (2, String::new()));
+        assert_eq!(run_search(&["program_name", "boot", "-", "--until", "2025-09-10"], input), (0, String::new()));
+    }
+}
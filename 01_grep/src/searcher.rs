@@ -0,0 +1,146 @@
+use std::io::{self, BufRead};
+use std::ops::Range;
+
+use crate::matcher::Matcher;
+
+// one match, as handed to a `Sink`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match<'a> {
+    // 1-based line number
+    pub line: usize,
+    // 1-based column, counted in chars so CJK text lines up in editors
+    pub column: usize,
+    // byte range of the match in the whole (UTF-8) input
+    pub bytes: Range<usize>,
+    pub text: &'a str,
+    pub line_text: &'a str,
+    // edits between the query and `text`, 0 unless the matcher is fuzzy
+    pub distance: usize,
+}
+
+// receives matches as they are found; returning `false` stops the search
+pub trait Sink {
+    fn matched(&mut self, m: &Match) -> io::Result<bool>;
+}
+
+impl<F> Sink for F
+where
+    F: FnMut(&Match) -> io::Result<bool>,
+{
+    fn matched(&mut self, m: &Match) -> io::Result<bool> {
+        self(m)
+    }
+}
+
+// runs any `Matcher` over input and streams every match to a `Sink`, for
+// tools that embed the search instead of running the binary. Only one line
+// is held at a time.
+pub struct Searcher<M> {
+    matcher: M,
+}
+
+impl<M: Matcher> Searcher<M> {
+    pub fn new(matcher: M) -> Searcher<M> {
+        Searcher { matcher }
+    }
+
+    pub fn matcher(&self) -> &M {
+        &self.matcher
+    }
+
+    // bytes that are not UTF-8 are replaced, input in another encoding should
+    // go through `encoding::decode_reader` first
+    pub fn search_reader<R: BufRead, S: Sink>(&self, mut reader: R, mut sink: S) -> io::Result<()> {
+        let mut buf = Vec::new();
+        let mut index = 0;
+        let mut offset = 0;
+        loop {
+            buf.clear();
+            if reader.read_until(b'\n', &mut buf)? == 0 {
+                return Ok(());
+            }
+            let line = String::from_utf8_lossy(&buf);
+            let line = line.trim_end_matches(['\n', '\r']);
+            if !self.search_line(index, offset, line, &mut sink)? {
+                return Ok(());
+            }
+            index += 1;
+            offset += buf.len();
+        }
+    }
+
+    pub fn search_str<S: Sink>(&self, text: &str, sink: S) -> io::Result<()> {
+        self.search_reader(text.as_bytes(), sink)
+    }
+
+    // every non-overlapping match of one line; false once the sink said stop
+    fn search_line<S: Sink>(&self, index: usize, offset: usize, line: &str, sink: &mut S) -> io::Result<bool> {
+        let mut at = 0;
+        while at <= line.len() {
+            let hit = match self.matcher.find_at(line, at) {
+                Some(hit) => hit,
+                None => break,
+            };
+            // an empty match has nothing to point at, look again one char on
+            if hit.range.is_empty() {
+                at = hit.range.end + line[hit.range.end..].chars().next().map_or(1, char::len_utf8);
+                continue;
+            }
+            let found = Match {
+                line: index + 1,
+                column: line[..hit.range.start].chars().count() + 1,
+                bytes: offset + hit.range.start..offset + hit.range.end,
+                text: &line[hit.range.clone()],
+                line_text: line,
+                distance: hit.distance,
+            };
+            if !sink.matched(&found)? {
+                return Ok(false);
+            }
+            at = hit.range.end;
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::casefold::CaseInsensitive;
+    use crate::fuzzy::Fuzzy;
+
+    fn collect<M: Matcher>(matcher: M, text: &str) -> Vec<(usize, usize, Range<usize>, String, usize)> {
+        let mut found = Vec::new();
+        Searcher::new(matcher)
+            .search_str(text, |m: &Match| {
+                found.push((m.line, m.column, m.bytes.clone(), m.text.to_string(), m.distance));
+                Ok(true)
+            })
+            .unwrap();
+        found
+    }
+
+    #[test]
+    fn test_searcher_streams_matches() {
+        let text = "【广东】\r\n苹果17 ProMax promax\n无\n";
+        assert_eq!(
+            collect(CaseInsensitive::new("PROMAX"), text),
+            vec![(2, 6, 23..29, "ProMax".into(), 0), (2, 13, 30..36, "promax".into(), 0)]
+        );
+        assert_eq!(collect(Fuzzy::new("ProMux", 1, true), text), vec![(2, 6, 23..29, "ProMax".into(), 1)]);
+    }
+
+    #[test]
+    fn test_searcher_sink_stops() {
+        let mut seen = 0;
+        Searcher::new(regex::Regex::new(r"\d+").unwrap())
+            .search_str("1 2\n3", |_: &Match| {
+                seen += 1;
+                Ok(seen < 2)
+            })
+            .unwrap();
+        assert_eq!(seen, 2);
+        // empty matches are skipped, not reported at every position
+        assert_eq!(collect(regex::Regex::new(r"\d*").unwrap(), "a1b"), vec![(1, 2, 1..2, "1".into(), 0)]);
+    }
+}
@@ -0,0 +1,234 @@
+use std::io::{self, Write};
+use std::sync::OnceLock;
+
+use regex::Regex;
+use serde::Serialize;
+
+// a value that is itself a link is parsed again, this deep at most
+const MAX_DEPTH: usize = 8;
+
+// a link found in a line, with its query parameters decoded
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Link {
+    // the link as it is written in the line
+    pub url: String,
+    // scheme, host and path: everything before the query
+    pub base: String,
+    pub params: Vec<Param>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Param {
+    // the names of the parameters the value was nested in and its own,
+    // joined with dots: `netUniBackUrl.productCode`
+    pub path: String,
+    pub name: String,
+    // percent-decoded
+    pub value: String,
+}
+
+// --param NAME, or NAME=VALUE to only keep links where the parameter has that value
+#[derive(Debug, PartialEq)]
+pub struct ParamFilter {
+    name: String,
+    value: Option<String>,
+}
+
+impl ParamFilter {
+    pub fn parse(spec: &str) -> ParamFilter {
+        match spec.split_once('=') {
+            Some((name, value)) => ParamFilter { name: name.to_string(), value: Some(value.to_string()) },
+            None => ParamFilter { name: spec.to_string(), value: None },
+        }
+    }
+
+    // a nested parameter can be named by its own name or its whole path
+    fn names(&self, param: &Param) -> bool {
+        param.name == self.name || param.path == self.name
+    }
+}
+
+// every http(s) link in `line`, in order
+pub fn find_links(line: &str) -> Vec<Link> {
+    static LINK: OnceLock<Regex> = OnceLock::new();
+    // a link ends at whitespace, quotes or the punctuation that ends CJK clauses
+    let link = LINK.get_or_init(|| Regex::new(r#"https?://[^\s"'<>，。；、）】]+"#).unwrap());
+    link.find_iter(line).map(|m| parse(m.as_str())).collect()
+}
+
+pub fn parse(url: &str) -> Link {
+    let (base, query) = split_query(url);
+    let mut params = Vec::new();
+    collect_params(query, "", 0, &mut params);
+    Link { url: url.to_string(), base: base.to_string(), params }
+}
+
+// the query may come after a `#`, single page apps put it in the fragment
+fn split_query(url: &str) -> (&str, &str) {
+    match url.split_once('?') {
+        Some((base, query)) => (base, query.split('#').next().unwrap_or("")),
+        None => (url, ""),
+    }
+}
+
+fn collect_params(query: &str, prefix: &str, depth: usize, params: &mut Vec<Param>) {
+    for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let name = percent_decode(name);
+        let value = percent_decode(value);
+        let path = if prefix.is_empty() { name.clone() } else { format!("{}.{}", prefix, name) };
+        // a link in a parameter, like a login redirect, has parameters of its own
+        let is_link = depth < MAX_DEPTH && (value.starts_with("http://") || value.starts_with("https://"));
+        let nested = if is_link { split_query(&value).1.to_string() } else { String::new() };
+        params.push(Param { path: path.clone(), name, value });
+        collect_params(&nested, &path, depth + 1, params);
+    }
+}
+
+// `%3D` to `=`; a `%` that does not start an escape is kept as it is, and
+// bytes that do not form UTF-8 are replaced
+pub fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escape = if bytes[i] == b'%' && i + 2 < bytes.len() {
+            hex_digit(bytes[i + 1]).zip(hex_digit(bytes[i + 2])).map(|(high, low)| high << 4 | low)
+        } else {
+            None
+        };
+        match escape {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn hex_digit(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|digit| digit as u8)
+}
+
+// the links that pass every NAME=VALUE filter; with filters, a link keeps
+// only the parameters they name and is dropped when it has none of them
+pub fn select(links: Vec<Link>, filters: &[ParamFilter]) -> Vec<Link> {
+    if filters.is_empty() {
+        return links;
+    }
+    links
+        .into_iter()
+        .filter(|link| {
+            filters.iter().filter(|f| f.value.is_some()).all(|f| {
+                link.params.iter().any(|p| f.names(p) && Some(&p.value) == f.value.as_ref())
+            })
+        })
+        .map(|link| Link { params: link.params.into_iter().filter(|p| filters.iter().any(|f| f.names(p))).collect(), ..link })
+        .filter(|link| !link.params.is_empty())
+        .collect()
+}
+
+// columns padded to their widest cell, the last one is not padded
+pub fn write_table<W: Write>(out: &mut W, header: &[&str], rows: &[Vec<String>]) -> io::Result<()> {
+    let mut widths: Vec<usize> = header.iter().map(|h| display_width(h)).collect();
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(display_width(cell));
+        }
+    }
+    let header: Vec<String> = header.iter().map(|h| h.to_string()).collect();
+    for row in std::iter::once(&header).chain(rows.iter()) {
+        let mut line = String::new();
+        for (i, cell) in row.iter().enumerate() {
+            line.push_str(cell);
+            if i + 1 < row.len() {
+                line.extend(std::iter::repeat_n(' ', widths[i] - display_width(cell) + 2));
+            }
+        }
+        writeln!(out, "{}", line.trim_end())?;
+    }
+    Ok(())
+}
+
+// terminal columns: CJK and full width chars take two
+pub fn display_width(text: &str) -> usize {
+    text.chars().map(char_width).sum()
+}
+
+pub fn char_width(c: char) -> usize {
+    match c as u32 {
+        0x1100..=0x115F | 0x2E80..=0xA4CF | 0xAC00..=0xD7A3 | 0xF900..=0xFAFF | 0xFE30..=0xFE4F | 0xFF00..=0xFF60 | 0xFFE0..=0xFFE6 | 0x20000..=0x3FFFD => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINE: &str = "苹果17 单点链接：https://wx.10086.cn/website/sso/netUni?netUniBackUrl=https%3A%2F%2Fwx.10086.cn%2Fwebsite%2FbusinessOnline%2FshopDetail%3FproductCode%3D2025090916365395401379458&net_token=移动认证";
+
+    fn params(link: &Link) -> Vec<(&str, &str)> {
+        link.params.iter().map(|p| (p.path.as_str(), p.value.as_str())).collect()
+    }
+
+    #[test]
+    fn test_find_links_nested_params() {
+        let links = find_links(LINE);
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].base, "https://wx.10086.cn/website/sso/netUni");
+        assert_eq!(
+            params(&links[0]),
+            vec![
+                ("netUniBackUrl", "https://wx.10086.cn/website/businessOnline/shopDetail?productCode=2025090916365395401379458"),
+                ("netUniBackUrl.productCode", "2025090916365395401379458"),
+                ("net_token", "移动认证"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_links_query_in_fragment() {
+        let line = "苹果17单点链接：https：https://gd.10086.cn/gdshop/qdxsd/index.html#/pageC/zdGoodsDetails/index?id=1967476646349086720&orderSource=1378，完";
+        let links = find_links(line);
+        assert_eq!(links.len(), 1);
+        assert_eq!(params(&links[0]), vec![("id", "1967476646349086720"), ("orderSource", "1378")]);
+    }
+
+    #[test]
+    fn test_percent_decode() {
+        assert_eq!(percent_decode("a%3Db%26c"), "a=b&c");
+        assert_eq!(percent_decode("%E5%B9%BF%E4%B8%9C"), "广东");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz%4"), "%zz%4");
+    }
+
+    #[test]
+    fn test_select() {
+        let filters = [ParamFilter::parse("productCode")];
+        let links = select(find_links(LINE), &filters);
+        assert_eq!(params(&links[0]), vec![("netUniBackUrl.productCode", "2025090916365395401379458")]);
+
+        let filters = [ParamFilter::parse("productCode=1"), ParamFilter::parse("net_token")];
+        assert!(select(find_links(LINE), &filters).is_empty());
+    }
+
+    #[test]
+    fn test_write_table() {
+        let mut out = Vec::new();
+        let rows = vec![
+            vec!["2".to_string(), "【广东】".to_string(), "id".to_string(), "1".to_string()],
+            vec!["12".to_string(), "x".to_string(), "activityId".to_string(), String::new()],
+        ];
+        write_table(&mut out, &["LINE", "SECTION", "PARAM", "VALUE"], &rows).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "LINE  SECTION   PARAM       VALUE\n2     【广东】  id          1\n12    x         activityId\n"
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::path::{Path, PathBuf};
+
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::types::{Types, TypesBuilder};
+use ignore::WalkBuilder;
+
+// directories that are never worth searching, and our own index file
+const SKIP_NAMES: [&str; 3] = [".git", "target", crate::index::INDEX_FILE];
+
+// expand a path into the files to search: a file is returned as is,
+// a directory is walked recursively honouring .gitignore / .ignore files.
+// An entry that cannot be read, like a locked subdirectory, is reported and
+// the walk goes on; returns the files and how many entries failed
+pub fn collect_files(path: &Path) -> (Vec<PathBuf>, usize) {
+    if !path.is_dir() {
+        return (vec![path.to_path_buf()], 0);
+    }
+
+    let mut files = Vec::new();
+    let walker = WalkBuilder::new(path)
+        .hidden(false)
+        .require_git(false)
+        .filter_entry(|entry| {
+            let name = entry.file_name().to_string_lossy();
+            !SKIP_NAMES.contains(&name.as_ref())
+        })
+        .build();
+
+    let mut failed = 0;
+    for entry in walker {
+        match entry {
+            Ok(entry) if entry.file_type().is_some_and(|t| t.is_file()) => files.push(entry.into_path()),
+            Ok(_) => {}
+            Err(err) => {
+                eprintln!("{}", err);
+                failed += 1;
+            }
+        }
+    }
+    // walk order depends on the file system, keep output reproducible
+    files.sort();
+    (files, failed)
+}
+
+// --glob and --type: which of the files walked under `root` get searched.
+// A glob is matched like a .gitignore line, relative to `root`, and one
+// starting with `!` skips the files it matches; type names are the ones
+// ripgrep knows, like css, js, html or rust
+pub struct Filter {
+    globs: Override,
+    types: Types,
+}
+
+impl Filter {
+    pub fn new(root: &Path, globs: &[String], types: &[String]) -> Result<Filter, ignore::Error> {
+        let mut overrides = OverrideBuilder::new(root);
+        for glob in globs {
+            overrides.add(glob)?;
+        }
+        let mut selected = TypesBuilder::new();
+        selected.add_defaults();
+        for name in types {
+            selected.select(name);
+        }
+        Ok(Filter { globs: overrides.build()?, types: selected.build()? })
+    }
+
+    pub fn allows(&self, file: &Path) -> bool {
+        !self.globs.matched(file, false).is_ignore() && !self.types.matched(file, false).is_ignore()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_collect_files_skips_ignored() {
+        let dir = std::env::temp_dir().join(format!("rust_demo_walk_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::create_dir_all(dir.join("target")).unwrap();
+        fs::write(dir.join("src/a.txt"), "a").unwrap();
+        fs::write(dir.join("b.txt"), "b").unwrap();
+        fs::write(dir.join("skip.log"), "log").unwrap();
+        fs::write(dir.join(".gitignore"), "*.log\n").unwrap();
+        fs::write(dir.join(".git/config"), "git").unwrap();
+        fs::write(dir.join("target/out.txt"), "out").unwrap();
+
+        let (files, failed) = collect_files(&dir);
+        assert_eq!(failed, 0);
+        let names: Vec<_> = files
+            .iter()
+            .map(|f| f.strip_prefix(&dir).unwrap().to_string_lossy().replace('\\', "/"))
+            .collect();
+        assert_eq!(names, vec![".gitignore", "b.txt", "src/a.txt"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_collect_files_goes_past_unreadable_dirs() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("ok")).unwrap();
+        fs::create_dir_all(dir.path().join("locked")).unwrap();
+        fs::write(dir.path().join("ok/a.txt"), "apple").unwrap();
+        fs::write(dir.path().join("locked/b.txt"), "apple").unwrap();
+        fs::set_permissions(dir.path().join("locked"), fs::Permissions::from_mode(0o000)).unwrap();
+        // root reads it anyway, there is nothing to test then
+        if fs::read_dir(dir.path().join("locked")).is_err() {
+            assert_eq!(collect_files(dir.path()), (vec![dir.path().join("ok/a.txt")], 1));
+        }
+        fs::set_permissions(dir.path().join("locked"), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[test]
+    fn test_filter() {
+        let root = Path::new("web");
+        let allowed = |globs: &[&str], types: &[&str]| {
+            let globs: Vec<String> = globs.iter().map(|s| s.to_string()).collect();
+            let types: Vec<String> = types.iter().map(|s| s.to_string()).collect();
+            let filter = Filter::new(root, &globs, &types).unwrap();
+            ["web/a.css", "web/js/app.js", "web/js/app.min.js", "web/logo.png", "web/index.html"]
+                .into_iter()
+                .filter(|f| filter.allows(Path::new(f)))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(allowed(&[], &[]).len(), 5);
+        assert_eq!(allowed(&["*.css", "*.js", "!*.min.js"], &[]), vec!["web/a.css", "web/js/app.js"]);
+        assert_eq!(allowed(&["!*.png"], &[]), vec!["web/a.css", "web/js/app.js", "web/js/app.min.js", "web/index.html"]);
+        assert_eq!(allowed(&["!*.min.js"], &["css", "js"]), vec!["web/a.css", "web/js/app.js"]);
+        assert_eq!(allowed(&["js/*"], &[]), vec!["web/js/app.js", "web/js/app.min.js"]);
+        assert!(Filter::new(root, &[], &[String::from("no-such-type")]).is_err());
+        assert!(Filter::new(root, &[String::from("a{b")], &[]).is_err());
+    }
+}